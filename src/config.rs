@@ -1,4 +1,8 @@
+use std::path::PathBuf;
+
 use clap::{App, Arg, ArgMatches, AppSettings, SubCommand};
+use scrubjay::package::GlobalConfig;
+use scrubjay::Options;
 
 use super::error::Error;
 
@@ -6,19 +10,51 @@ pub enum Action {
     Install,
     Uninstall,
     Reinstall,
+    Status,
+    RestoreBackup,
+    Adopt,
+    List,
+    Prune,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Human,
+    Json,
+    JsonLines,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ListFilter {
+    All,
+    Installed,
+    Available,
 }
 
-const ARG_DRY_RUN: &'static str = "dry-run";
-const ARG_FORCE: &'static str = "force";
-const ARG_PACKAGES: &'static str = "packages";
-const ARG_VERBOSE: &'static str = "verbose";
+const ARG_AVAILABLE: &str = "available";
+const ARG_BACKUP_DIR: &str = "backup-dir";
+const ARG_CONFIGURED: &str = "configured";
+const ARG_DRY_RUN: &str = "dry-run";
+const ARG_FORCE: &str = "force";
+const ARG_FORMAT: &str = "format";
+const ARG_INSTALLED: &str = "installed";
+const ARG_PACKAGES: &str = "packages";
+const ARG_REPLACE_DANGLING: &str = "replace-dangling";
+const ARG_TARGET: &str = "target";
+const ARG_VERBOSE: &str = "verbose";
 
 pub struct Config {
     pub action: Action,
     pub package_names: Vec<String>,
     pub dry_run: bool,
     pub force: bool,
+    pub replace_dangling: bool,
     pub verbose: bool,
+    pub format: Format,
+    pub list_filter: ListFilter,
+    pub configured_only: bool,
+    pub target: Option<String>,
+    pub backup_dir: Option<String>,
 }
 
 pub fn build_app() -> App<'static, 'static> {
@@ -36,6 +72,28 @@ pub fn build_app() -> App<'static, 'static> {
         "Allows existing files to be overwritten or deleted",
     );
 
+    let replace_dangling_arg = Arg::with_name(ARG_REPLACE_DANGLING)
+        .long(ARG_REPLACE_DANGLING)
+        .help("Allows dangling symlinks to be replaced");
+
+    let backup_dir_arg = Arg::with_name(ARG_BACKUP_DIR)
+        .long(ARG_BACKUP_DIR)
+        .value_name("DIR")
+        .help("Sets where files replaced with --force are backed up");
+
+    let target_arg = Arg::with_name(ARG_TARGET)
+        .long(ARG_TARGET)
+        .short("t")
+        .value_name("DIR")
+        .help("Overrides the directory the package(s) are installed to");
+
+    let format_arg = Arg::with_name(ARG_FORMAT)
+        .long(ARG_FORMAT)
+        .value_name("FORMAT")
+        .possible_values(&["human", "json", "jsonl"])
+        .default_value("human")
+        .help("Sets the output format (JSON Lines streams one object per event)");
+
     let verbose_arg = Arg::with_name(ARG_VERBOSE)
         .long(ARG_VERBOSE)
         .short("v")
@@ -45,11 +103,11 @@ pub fn build_app() -> App<'static, 'static> {
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
+        .setting(AppSettings::SubcommandRequiredElseHelp)
         .global_settings(
             &[
                 AppSettings::ColorAuto,
                 AppSettings::ColoredHelp,
-                AppSettings::SubcommandRequiredElseHelp,
                 AppSettings::VersionlessSubcommands,
             ],
         )
@@ -57,24 +115,103 @@ pub fn build_app() -> App<'static, 'static> {
             SubCommand::with_name("install")
                 .about("Installs the provided package(s)")
                 .arg(packages_arg.clone())
+                .arg(target_arg.clone())
                 .arg(dry_run_arg.clone())
                 .arg(force_arg.clone())
+                .arg(replace_dangling_arg.clone())
+                .arg(backup_dir_arg.clone())
+                .arg(format_arg.clone())
+                .arg(verbose_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("adopt")
+                .about(
+                    "Moves existing files into the provided package(s), then installs them",
+                )
+                .arg(packages_arg.clone())
+                .arg(target_arg.clone())
+                .arg(dry_run_arg.clone())
+                .arg(replace_dangling_arg.clone())
+                .arg(format_arg.clone())
                 .arg(verbose_arg.clone()),
         )
         .subcommand(
             SubCommand::with_name("uninstall")
                 .about("Uninstalls the provided package(s)")
                 .arg(packages_arg.clone())
+                .arg(target_arg.clone())
                 .arg(dry_run_arg.clone())
                 .arg(force_arg.clone())
+                .arg(format_arg.clone())
                 .arg(verbose_arg.clone()),
         )
         .subcommand(
             SubCommand::with_name("reinstall")
                 .about("Reinstalls the provided package(s)")
                 .arg(packages_arg.clone())
+                .arg(target_arg.clone())
+                .arg(dry_run_arg.clone())
+                .arg(force_arg.clone())
+                .arg(replace_dangling_arg.clone())
+                .arg(backup_dir_arg.clone())
+                .arg(format_arg.clone())
+                .arg(verbose_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("Shows whether the provided package(s) are installed")
+                .arg(packages_arg.clone())
+                .arg(target_arg.clone())
+                .arg(format_arg.clone())
+                .arg(verbose_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Lists the packages in the packages root and whether they are installed")
+                .arg(
+                    Arg::with_name(ARG_INSTALLED)
+                        .long(ARG_INSTALLED)
+                        .conflicts_with(ARG_AVAILABLE)
+                        .help("Only lists packages that are fully or partially installed"),
+                )
+                .arg(
+                    Arg::with_name(ARG_AVAILABLE)
+                        .long(ARG_AVAILABLE)
+                        .help("Only lists packages that are not installed"),
+                )
+                .arg(
+                    Arg::with_name(ARG_CONFIGURED)
+                        .long(ARG_CONFIGURED)
+                        .help("Only lists directories with a .scrubjay.toml file"),
+                )
+                .arg(target_arg.clone())
+                .arg(format_arg.clone())
+                .arg(verbose_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("prune")
+                .about(
+                    "Removes dangling links into known packages, and directories left empty by them",
+                )
+                .arg(
+                    target_arg
+                        .clone()
+                        .help("Sets the directory to prune instead of the default target"),
+                )
+                .arg(dry_run_arg.clone())
+                .arg(format_arg.clone())
+                .arg(verbose_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("restore-backup")
+                .about(
+                    "Uninstalls the provided package(s) and restores the files they replaced",
+                )
+                .arg(packages_arg.clone())
+                .arg(target_arg.clone())
                 .arg(dry_run_arg.clone())
                 .arg(force_arg.clone())
+                .arg(format_arg.clone())
                 .arg(verbose_arg.clone()),
         )
 }
@@ -85,23 +222,63 @@ impl Config {
         let matches = app.get_matches_safe()?;
         let (action, submatches) = match matches.subcommand() {
             ("install", Some(submatches)) => (Action::Install, submatches),
+            ("adopt", Some(submatches)) => (Action::Adopt, submatches),
             ("uninstall", Some(submatches)) => (Action::Uninstall, submatches),
             ("reinstall", Some(submatches)) => (Action::Reinstall, submatches),
+            ("status", Some(submatches)) => (Action::Status, submatches),
+            ("restore-backup", Some(submatches)) => (Action::RestoreBackup, submatches),
+            ("list", Some(submatches)) => (Action::List, submatches),
+            ("prune", Some(submatches)) => (Action::Prune, submatches),
             _ => unreachable!(),
         };
 
         Ok(Config {
-            action: action,
+            action,
             package_names: package_names(submatches),
+            list_filter: if submatches.is_present(ARG_INSTALLED) {
+                ListFilter::Installed
+            } else if submatches.is_present(ARG_AVAILABLE) {
+                ListFilter::Available
+            } else {
+                ListFilter::All
+            },
+            configured_only: submatches.is_present(ARG_CONFIGURED),
             dry_run: submatches.is_present(ARG_DRY_RUN),
             force: submatches.is_present(ARG_FORCE),
+            replace_dangling: submatches.is_present(ARG_REPLACE_DANGLING),
             verbose: submatches.is_present(ARG_VERBOSE),
+            format: match submatches.value_of(ARG_FORMAT) {
+                Some("json") => Format::Json,
+                Some("jsonl") => Format::JsonLines,
+                _ => Format::Human,
+            },
+            target: submatches.value_of(ARG_TARGET).map(String::from),
+            backup_dir: submatches.value_of(ARG_BACKUP_DIR).map(String::from),
         })
     }
+
+    /// Combines the command-line flags with the defaults in `global_config`.
+    pub fn options(&self, global_config: &GlobalConfig) -> Options {
+        let mut options = Options::new()
+            .dry_run(self.dry_run)
+            .force(self.force)
+            .replace_dangling(self.replace_dangling)
+            .verbose(self.verbose || global_config.verbose.unwrap_or(false))
+            .backup(global_config.backup.unwrap_or(true))
+            .defaults(global_config.package_defaults());
+
+        if let Some(ref target) = self.target {
+            options = options.target(PathBuf::from(target));
+        }
+
+        if let Some(ref backup_dir) = self.backup_dir {
+            options = options.backup_dir(PathBuf::from(backup_dir));
+        }
+
+        options
+    }
 }
 
 fn package_names<'a>(matches: &ArgMatches<'a>) -> Vec<String> {
-    matches.values_of_lossy(ARG_PACKAGES).expect(
-        "Argument specification is inconsistent",
-    )
+    matches.values_of_lossy(ARG_PACKAGES).unwrap_or_default()
 }
@@ -3,7 +3,7 @@ use std::process;
 use clap;
 use colored::*;
 
-use super::package;
+use scrubjay::package;
 
 #[derive(Debug)]
 pub enum Error {
@@ -16,7 +16,7 @@ impl Error {
         match self {
             Error::ArgError(error) => error.exit(),
             Error::PackageError(error) => {
-                println!("{} {}", "error:".red().bold(), error);
+                eprintln!("{} {}", "error:".red().bold(), error);
                 process::exit(1)
             }
         }
@@ -1,5 +1,3 @@
-extern crate clap;
-extern crate colored;
 extern crate ignore;
 extern crate toml;
 extern crate serde;
@@ -7,8 +5,6 @@ extern crate serde;
 extern crate serde_derive;
 extern crate shellexpand;
 
-pub mod config;
-pub mod error;
 pub mod package;
 
-pub use self::config::Config;
+pub use self::package::{Options, Package};
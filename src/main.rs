@@ -1,33 +1,194 @@
+extern crate clap;
+extern crate colored;
 extern crate scrubjay;
+extern crate serde_json;
 
-use std::path::PathBuf;
+mod config;
+mod error;
+mod report;
 
-use scrubjay::config::{Action, Config};
-use scrubjay::error::Error;
-use scrubjay::package::Package;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
 
-fn main_safe() -> Result<(), Error> {
+use scrubjay::package::{self, Event, Executor, GlobalConfig, InstallStatus, Package, Plan,
+                        Reporter, State};
+use scrubjay::Options;
+
+use config::{Action, Config, Format, ListFilter};
+use error::Error;
+use report::{HumanReporter, JsonLinesReporter, JsonReporter};
+
+fn main_safe() -> Result<bool, Error> {
     let config = Config::from_args()?;
-    if config.dry_run {
+    let global_config = GlobalConfig::load()?;
+    let options = config.options(&global_config);
+
+    let reporter: Box<dyn Reporter> = match config.format {
+        Format::Human => Box::new(HumanReporter::new(options.dry_run, options.verbose)),
+        Format::Json => Box::new(JsonReporter::new()),
+        Format::JsonLines => Box::new(JsonLinesReporter),
+    };
+
+    if config.dry_run && config.format == Format::Human {
         println!("(Performing dry run)");
     }
 
-    for package_name in config.package_names.iter() {
-        let package_path = PathBuf::from(package_name);
-        let package = Package::new(&package_path, &config)?;
+    let options = options.reporter(reporter);
+    let result = match config.action {
+        Action::List => list_packages(&config, &global_config, &options),
+        Action::Prune => prune_target(&global_config, &options),
+        _ => run_packages(&config, &global_config, &options),
+    };
+
+    options.reporter.finish();
+    Ok(result?)
+}
+
+fn run_packages(
+    config: &Config,
+    global_config: &GlobalConfig,
+    options: &Options,
+) -> package::Result<bool> {
+    let executor = Executor::new(options);
+    let mut in_sync = true;
+
+    let package_paths = match ordered_package_paths(config, global_config, options) {
+        Ok(package_paths) => package_paths,
+        Err(error) => {
+            options.reporter.report(&Event::failed(&error));
+            return Err(error);
+        }
+    };
+
+    for package_path in package_paths {
+        match run_package(config, options, &executor, &package_path) {
+            Ok(package_in_sync) => in_sync &= package_in_sync,
+            Err(error) => {
+                options.reporter.report(&Event::package_failed(&package_path, &error));
+                return Err(error);
+            }
+        }
+    }
+
+    Ok(in_sync)
+}
+
+/// Resolves the configured package names to paths, in the order the action
+/// should be performed on them.
+fn ordered_package_paths(
+    config: &Config,
+    global_config: &GlobalConfig,
+    options: &Options,
+) -> package::Result<Vec<PathBuf>> {
+    let package_paths = config
+        .package_names
+        .iter()
+        .map(|package_name| global_config.package_path(package_name))
+        .collect::<package::Result<Vec<_>>>()?;
+
+    match config.action {
+        Action::Install => package::install_order(&package_paths, global_config),
+        Action::Uninstall | Action::RestoreBackup => {
+            package::uninstall_order(&package_paths, global_config, options.force)
+        }
+        _ => Ok(package_paths),
+    }
+}
+
+/// Reports each package in the packages root (or the current directory if
+/// there isn't one) that matches the configured filter.
+fn list_packages(
+    config: &Config,
+    global_config: &GlobalConfig,
+    options: &Options,
+) -> package::Result<bool> {
+    let packages_root = match global_config.packages_root()? {
+        Some(packages_root) => packages_root,
+        None => env::current_dir()?,
+    };
+
+    for package_path in package::find_packages(&packages_root, config.configured_only)? {
+        let package = Package::new(&package_path, options)?;
+        let status = package.install_status()?;
 
-        match config.action {
-            Action::Install => package.install()?,
-            Action::Uninstall => package.uninstall()?,
-            Action::Reinstall => package.reinstall()?,
+        let is_match = match config.list_filter {
+            ListFilter::All => true,
+            ListFilter::Installed => status != InstallStatus::NotInstalled,
+            ListFilter::Available => status == InstallStatus::NotInstalled,
         };
+
+        if is_match {
+            options.reporter.report(&Event::PackageListed {
+                name: package.name(),
+                package: package_path,
+                status,
+            });
+        }
+    }
+
+    Ok(true)
+}
+
+/// Prunes the target directory (or the default one) of dangling links into
+/// installed packages or the packages in the packages root.
+fn prune_target(global_config: &GlobalConfig, options: &Options) -> package::Result<bool> {
+    let mut target = match options.target {
+        Some(ref target) => target.clone(),
+        None => global_config.target_root()?,
+    };
+
+    if target.is_relative() {
+        target = env::current_dir()?.join(target);
     }
 
-    Ok(())
+    let package_paths: Vec<PathBuf> = match global_config.packages_root()? {
+        Some(ref packages_root) if packages_root.is_dir() => {
+            package::find_packages(packages_root, false)?
+        }
+        _ => Vec::new(),
+    };
+
+    let mut plan = Plan::new(package::Action::Prune, &target, &target);
+    let result = package::plan_prune(&mut plan, &package_paths, &State::load()?)
+        .and_then(|()| plan.execute(&Executor::new(options)));
+
+    if let Err(error) = result {
+        options.reporter.report(&Event::package_failed(&target, &error));
+        return Err(error);
+    }
+
+    Ok(true)
+}
+
+/// Performs the configured action on one package, returning whether it is
+/// in sync if the action is `status`.
+fn run_package(
+    config: &Config,
+    options: &Options,
+    executor: &Executor,
+    package_path: &Path,
+) -> package::Result<bool> {
+    let package = Package::new(package_path, options)?;
+
+    let action = match config.action {
+        Action::Install => package::Action::Install,
+        Action::Adopt => package::Action::Adopt,
+        Action::Uninstall => package::Action::Uninstall,
+        Action::Reinstall => package::Action::Reinstall,
+        Action::RestoreBackup => package::Action::RestoreBackup,
+        Action::Status => return package.status(),
+        Action::List | Action::Prune => unreachable!(),
+    };
+
+    package.plan(action)?.execute(executor)?;
+    Ok(true)
 }
 
 fn main() {
-    if let Err(error) = main_safe() {
-        error.exit();
+    match main_safe() {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(error) => error.exit(),
     }
 }
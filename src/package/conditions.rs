@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Separates a file name from the conditions under which it's installed, as
+/// in `.gitconfig##host.work` or `.profile##os.linux,arch.x86_64`.
+pub const SEPARATOR: &str = "##";
+
+const HOSTNAME_PATHS: [&str; 2] = ["/proc/sys/kernel/hostname", "/etc/hostname"];
+
+/// What conditions are evaluated against.
+#[derive(Debug, Clone, Default)]
+pub struct Facts {
+    pub hostname: String,
+    pub user: String,
+    pub os: String,
+    pub arch: String,
+    pub vars: BTreeMap<String, String>,
+}
+
+impl Facts {
+    pub fn current(vars: &BTreeMap<String, String>) -> Facts {
+        Facts {
+            hostname: hostname(),
+            user: env::var("USER").or_else(|_| env::var("LOGNAME")).unwrap_or_default(),
+            os: env::consts::OS.to_owned(),
+            arch: env::consts::ARCH.to_owned(),
+            vars: vars.clone(),
+        }
+    }
+
+    /// Scores a comma-separated list of conditions, or returns `None` if any
+    /// of them don't hold. More specific conditions score higher.
+    pub fn score(&self, conditions_str: &str) -> Option<u32> {
+        let mut score = 0;
+
+        for condition in conditions_str.split(',') {
+            let mut parts = condition.splitn(2, '.');
+            let class = parts.next().unwrap_or_default();
+            let value = parts.next();
+
+            let (matches, weight) = match (class, value) {
+                ("default", None) | ("d", None) => (true, 0),
+                ("os", Some(value)) | ("o", Some(value)) => (value == self.os, 1),
+                ("arch", Some(value)) | ("a", Some(value)) => (value == self.arch, 2),
+                ("user", Some(value)) | ("u", Some(value)) => (value == self.user, 8),
+                ("hostname", Some(value)) | ("host", Some(value)) | ("h", Some(value)) => {
+                    (value.eq_ignore_ascii_case(&self.hostname), 16)
+                }
+                (name, Some(value)) => (self.vars.get(name).is_some_and(|var| var == value), 4),
+                (_, None) => (false, 0),
+            };
+
+            if !matches {
+                return None;
+            }
+
+            score += weight;
+        }
+
+        Some(score)
+    }
+}
+
+/// Splits a file name into its unconditional name and its conditions, if it
+/// has any.
+pub fn split_name(file_name: &str) -> (&str, Option<&str>) {
+    match file_name.find(SEPARATOR) {
+        Some(index) if index > 0 => (&file_name[..index], Some(&file_name[index + SEPARATOR.len()..])),
+        _ => (file_name, None),
+    }
+}
+
+/// Removes the conditions from each component of `path`.
+pub fn strip_conditions(path: &Path) -> PathBuf {
+    path.iter()
+        .map(|component| {
+            let component_str = component.to_string_lossy();
+            split_name(&component_str).0.to_owned()
+        })
+        .collect()
+}
+
+/// Whether the last component of `path` has conditions.
+pub fn is_conditional(path: &Path) -> bool {
+    path.file_name().is_some_and(|file_name| split_name(&file_name.to_string_lossy()).1.is_some())
+}
+
+/// Whether `path` is the best match among itself and its siblings with the
+/// same unconditional name, and its own conditions hold.
+pub fn is_best_match(path: &Path, facts: &Facts) -> bool {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().into_owned(),
+        None => return true,
+    };
+
+    let (name, conditions) = split_name(&file_name);
+    let score = match conditions {
+        Some(conditions_str) => match facts.score(conditions_str) {
+            Some(score) => score,
+            None => return false,
+        },
+        None => 0,
+    };
+
+    let siblings = match path.parent().map(fs::read_dir) {
+        Some(Ok(siblings)) => siblings,
+        _ => return true,
+    };
+
+    for sibling in siblings.filter_map(|entry_result| entry_result.ok()) {
+        let sibling_name = sibling.file_name().to_string_lossy().into_owned();
+        if sibling_name == file_name {
+            continue;
+        }
+
+        let (other_name, other_conditions) = split_name(&sibling_name);
+        if other_name != name || (conditions.is_none() && other_conditions.is_none()) {
+            continue;
+        }
+
+        let other_score = match other_conditions {
+            Some(conditions_str) => facts.score(conditions_str),
+            None => Some(0),
+        };
+
+        // Ties go to whichever name sorts first, i.e. the unconditional one
+        if let Some(other_score) = other_score {
+            if other_score > score || (other_score == score && sibling_name < file_name) {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn hostname() -> String {
+    let hostname = HOSTNAME_PATHS
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|contents| contents.trim().to_owned())
+        .find(|contents| !contents.is_empty())
+        .or_else(|| env::var("HOSTNAME").ok())
+        .unwrap_or_default();
+
+    // Only the first label, so `work` matches `work.example.com`
+    hostname.split('.').next().unwrap_or_default().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testing::TempDir;
+
+    fn facts() -> Facts {
+        let mut facts = Facts {
+            hostname: "work".to_owned(),
+            user: "jordan".to_owned(),
+            os: "linux".to_owned(),
+            arch: "x86_64".to_owned(),
+            ..Facts::default()
+        };
+        facts.vars.insert("shell".to_owned(), "fish".to_owned());
+        facts
+    }
+
+    /// Creates an empty file for each of `file_names` in a fresh directory.
+    fn dir_with(name: &str, file_names: &[&str]) -> TempDir {
+        let dir = TempDir::new(name);
+        for file_name in file_names {
+            dir.file(file_name, "");
+        }
+
+        dir
+    }
+
+    #[test]
+    fn scores_matching_conditions_by_specificity() {
+        let facts = facts();
+        assert_eq!(facts.score("default"), Some(0));
+        assert_eq!(facts.score("os.linux"), Some(1));
+        assert_eq!(facts.score("a.x86_64"), Some(2));
+        assert_eq!(facts.score("shell.fish"), Some(4));
+        assert_eq!(facts.score("u.jordan"), Some(8));
+        assert_eq!(facts.score("host.WORK"), Some(16));
+        assert_eq!(facts.score("os.linux,hostname.work"), Some(17));
+    }
+
+    #[test]
+    fn rejects_conditions_that_dont_hold() {
+        let facts = facts();
+        assert_eq!(facts.score("os.darwin"), None);
+        assert_eq!(facts.score("os.linux,h.home"), None);
+        assert_eq!(facts.score("shell.zsh"), None);
+        assert_eq!(facts.score("editor.nvim"), None);
+        assert_eq!(facts.score("os"), None);
+    }
+
+    #[test]
+    fn splits_names() {
+        assert_eq!(split_name(".profile##os.linux"), (".profile", Some("os.linux")));
+        assert_eq!(split_name(".profile"), (".profile", None));
+        assert_eq!(split_name("##os.linux"), ("##os.linux", None));
+        assert_eq!(
+            strip_conditions(Path::new(".config##os.linux/fish##host.work/config.fish")),
+            PathBuf::from(".config/fish/config.fish")
+        );
+    }
+
+    #[test]
+    fn prefers_the_most_specific_match() {
+        let dir = dir_with("specific", &[".profile", ".profile##os.linux", ".profile##h.work"]);
+        let facts = facts();
+        assert!(!is_best_match(&dir.join(".profile"), &facts));
+        assert!(!is_best_match(&dir.join(".profile##os.linux"), &facts));
+        assert!(is_best_match(&dir.join(".profile##h.work"), &facts));
+    }
+
+    #[test]
+    fn never_matches_conditions_that_dont_hold() {
+        let dir = dir_with("unmatched", &[".profile##os.darwin"]);
+        assert!(!is_best_match(&dir.join(".profile##os.darwin"), &facts()));
+    }
+
+    #[test]
+    fn breaks_ties_by_name() {
+        let dir = dir_with("tie", &[".profile##o.linux", ".profile##os.linux"]);
+        let facts = facts();
+        assert!(is_best_match(&dir.join(".profile##o.linux"), &facts));
+        assert!(!is_best_match(&dir.join(".profile##os.linux"), &facts));
+    }
+
+    #[test]
+    fn prefers_unconditional_names_to_defaults() {
+        let dir = dir_with("default", &[".profile", ".profile##default"]);
+        let facts = facts();
+        assert!(is_best_match(&dir.join(".profile"), &facts));
+        assert!(!is_best_match(&dir.join(".profile##default"), &facts));
+    }
+}
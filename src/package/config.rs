@@ -1,26 +1,47 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use toml;
 
 use super::{Error, Result};
 
-pub const DEFAULT_FILENAME: &'static str = ".scrubjay.toml";
+pub const DEFAULT_FILENAME: &str = ".scrubjay.toml";
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Hook {
     pub command: Option<String>,
     pub script: Option<String>,
+    /// Extra environment variables for the hook.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
 }
 
 impl Hook {
     fn script_name(&self) -> Option<&str> {
-        self.script.as_ref().map(String::as_str)
+        self.script.as_deref()
     }
 }
 
-#[derive(Debug, Default, Deserialize)]
+/// How a package's files are installed to their target paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Symlink,
+    Copy,
+    Hardlink,
+}
+
+impl Mode {
+    pub fn is_symlink(&self) -> bool {
+        *self == Mode::Symlink
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct Hooks {
     pub pre_install: Option<Hook>,
     pub post_install: Option<Hook>,
@@ -28,9 +49,28 @@ pub struct Hooks {
     pub post_uninstall: Option<Hook>,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct Config {
     pub target: Option<String>,
+    pub backup_dir: Option<String>,
+    pub mode: Option<Mode>,
+    /// Modes for the files matching each gitignore-style pattern, overriding
+    /// `mode`.
+    #[serde(default)]
+    pub modes: BTreeMap<String, Mode>,
+    /// Whether symlinks point to the package with relative paths, so they
+    /// survive the package and target being moved together.
+    pub relative: Option<bool>,
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    #[serde(default)]
+    pub depends: Vec<String>,
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+    #[serde(default)]
+    pub provides: Vec<String>,
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
     pub hooks: Option<Hooks>,
 }
 
@@ -40,11 +80,15 @@ macro_rules! hook_field {
     };
 }
 
-impl Config {
-    pub fn from_str(toml_str: &str) -> Result<Config> {
+impl FromStr for Config {
+    type Err = Error;
+
+    fn from_str(toml_str: &str) -> Result<Config> {
         toml::from_str(toml_str).map_err(Error::from)
     }
+}
 
+impl Config {
     pub fn from_file(file: &mut File) -> Result<Config> {
         let mut toml_str = String::new();
         file.read_to_string(&mut toml_str)?;
@@ -65,8 +109,32 @@ impl Config {
         Config::from_path(path)
     }
 
+    /// Fills in anything this config leaves unset from `defaults`, and adds
+    /// their ignore patterns and variables to its own.
+    pub fn merge(mut self, defaults: &Config) -> Config {
+        if self.target.is_none() {
+            self.target = defaults.target.clone();
+        }
+
+        if self.backup_dir.is_none() {
+            self.backup_dir = defaults.backup_dir.clone();
+        }
+
+        if self.relative.is_none() {
+            self.relative = defaults.relative;
+        }
+
+        self.ignore.extend(defaults.ignore.iter().cloned());
+
+        for (name, value) in &defaults.vars {
+            self.vars.entry(name.clone()).or_insert_with(|| value.clone());
+        }
+
+        self
+    }
+
     pub fn script_names(&self) -> Vec<&str> {
-        let hooks = vec![
+        let hooks = [
             hook_field!(self.hooks, pre_install),
             hook_field!(self.hooks, post_install),
             hook_field!(self.hooks, pre_uninstall),
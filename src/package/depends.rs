@@ -0,0 +1,250 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{Config, Error, GlobalConfig, Result, State};
+
+/// Orders packages so that each comes after the packages it depends on,
+/// including any dependencies that aren't installed yet.
+pub fn install_order(
+    package_paths: &[PathBuf],
+    global_config: &GlobalConfig,
+) -> Result<Vec<PathBuf>> {
+    let package_paths = canonicalize_all(package_paths)?;
+    let state = State::load()?;
+    let mut sorter = Sorter::new(global_config);
+
+    for package_path in &package_paths {
+        sorter.visit(package_path, &|_| true)?;
+    }
+
+    // Dependencies that are already installed are left alone
+    Ok(
+        sorter
+            .order
+            .into_iter()
+            .filter(|path| package_paths.contains(path) || state.record(path).is_none())
+            .collect(),
+    )
+}
+
+/// Orders packages so that each comes before the packages it depends on,
+/// failing if any other installed package still depends on one of them.
+pub fn uninstall_order(
+    package_paths: &[PathBuf],
+    global_config: &GlobalConfig,
+    force: bool,
+) -> Result<Vec<PathBuf>> {
+    let package_paths = canonicalize_all(package_paths)?;
+
+    if !force {
+        let state = State::load()?;
+        for record in &state.packages {
+            if package_paths.contains(&record.path) {
+                continue;
+            }
+
+            let dependencies = dependencies(&record.path, global_config).unwrap_or_default();
+            if let Some(dependency) = dependencies.iter().find(|path| package_paths.contains(path)) {
+                return Err(Error::DependedOnError(
+                    package_name(dependency),
+                    package_name(&record.path),
+                ));
+            }
+        }
+    }
+
+    let mut sorter = Sorter::new(global_config);
+    for package_path in &package_paths {
+        sorter.visit(package_path, &|path| package_paths.iter().any(|requested| requested == path))?;
+    }
+
+    sorter.order.reverse();
+    Ok(sorter.order)
+}
+
+/// The packages named in the `depends` key of a package's config, which are
+/// resolved like package names on the command line, or looked for next to it
+/// if there's no packages root.
+pub fn dependencies(package_path: &Path, global_config: &GlobalConfig) -> Result<Vec<PathBuf>> {
+    let config = Config::from_dir(package_path)?;
+    let has_packages_root = global_config.packages_root()?.is_some();
+
+    config
+        .depends
+        .iter()
+        .map(|dependency_name| {
+            let dependency_path = if has_packages_root {
+                global_config.package_path(dependency_name)?
+            } else {
+                package_path.parent().unwrap_or(package_path).join(dependency_name)
+            };
+
+            if dependency_path.is_dir() {
+                canonicalize(&dependency_path)
+            } else {
+                Err(Error::MissingDependencyError(
+                    package_name(package_path),
+                    dependency_name.clone(),
+                ))
+            }
+        })
+        .collect()
+}
+
+/// A depth-first topological sort that detects cycles.
+struct Sorter<'a> {
+    global_config: &'a GlobalConfig,
+    order: Vec<PathBuf>,
+    visited: BTreeSet<PathBuf>,
+    visiting: Vec<PathBuf>,
+}
+
+impl<'a> Sorter<'a> {
+    fn new(global_config: &'a GlobalConfig) -> Sorter<'a> {
+        Sorter {
+            global_config,
+            order: Vec::new(),
+            visited: BTreeSet::new(),
+            visiting: Vec::new(),
+        }
+    }
+
+    fn visit(&mut self, package_path: &Path, includes: &dyn Fn(&Path) -> bool) -> Result<()> {
+        if self.visited.contains(package_path) {
+            return Ok(());
+        }
+
+        if let Some(index) = self.visiting.iter().position(|path| path == package_path) {
+            let mut cycle: Vec<String> = self.visiting[index..]
+                .iter()
+                .map(|path| package_name(path))
+                .collect();
+            cycle.push(package_name(package_path));
+            return Err(Error::DependencyCycleError(cycle));
+        }
+
+        self.visiting.push(package_path.to_owned());
+        for dependency_path in dependencies(package_path, self.global_config)? {
+            if includes(&dependency_path) {
+                self.visit(&dependency_path, includes)?;
+            }
+        }
+        self.visiting.pop();
+
+        self.visited.insert(package_path.to_owned());
+        self.order.push(package_path.to_owned());
+        Ok(())
+    }
+}
+
+fn canonicalize_all(package_paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    package_paths.iter().map(|package_path| canonicalize(package_path)).collect()
+}
+
+fn canonicalize(package_path: &Path) -> Result<PathBuf> {
+    if !package_path.exists() {
+        return Err(Error::FileDoesNotExistError(package_path.to_owned()));
+    }
+
+    Ok(fs::canonicalize(package_path)?)
+}
+
+fn package_name(package_path: &Path) -> String {
+    package_path
+        .file_name()
+        .unwrap_or(package_path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::config;
+    use super::super::testing::TempDir;
+
+    /// Creates a package for each of `packages`, a path and the names it
+    /// depends on, in a fresh directory.
+    fn packages_dir(name: &str, packages: &[(&str, &[&str])]) -> TempDir {
+        let dir = TempDir::new(name);
+        for &(package_path, depends) in packages {
+            let depends: Vec<String> = depends.iter().map(|name| format!("{:?}", name)).collect();
+            let config_str = format!("depends = [{}]\n", depends.join(", "));
+            dir.file(Path::new(package_path).join(config::DEFAULT_FILENAME), &config_str);
+        }
+
+        dir
+    }
+
+    fn names(paths: &[PathBuf]) -> Vec<String> {
+        paths.iter().map(|path| package_name(path)).collect()
+    }
+
+    #[test]
+    fn orders_dependencies_first() {
+        let dir = packages_dir("order", &[("a", &["b", "c"]), ("b", &["c"]), ("c", &[])]);
+        let global_config = GlobalConfig::default();
+        let mut sorter = Sorter::new(&global_config);
+        sorter.visit(&dir.join("a"), &|_| true).unwrap();
+        assert_eq!(names(&sorter.order), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn skips_excluded_dependencies() {
+        let dir = packages_dir("excluded", &[("a", &["b"]), ("b", &[])]);
+        let a_path = dir.join("a");
+        let global_config = GlobalConfig::default();
+        let mut sorter = Sorter::new(&global_config);
+        sorter.visit(&a_path, &|path| path == a_path).unwrap();
+        assert_eq!(names(&sorter.order), ["a"]);
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let dir = packages_dir("cycle", &[("a", &["b"]), ("b", &["c"]), ("c", &["b"])]);
+        let global_config = GlobalConfig::default();
+        let mut sorter = Sorter::new(&global_config);
+        match sorter.visit(&dir.join("a"), &|_| true) {
+            Err(Error::DependencyCycleError(cycle)) => assert_eq!(cycle, ["b", "c", "b"]),
+            result => panic!("expected a dependency cycle, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reports_self_dependencies_as_cycles() {
+        let dir = packages_dir("self", &[("a", &["a"])]);
+        let global_config = GlobalConfig::default();
+        let mut sorter = Sorter::new(&global_config);
+        match sorter.visit(&dir.join("a"), &|_| true) {
+            Err(Error::DependencyCycleError(cycle)) => assert_eq!(cycle, ["a", "a"]),
+            result => panic!("expected a dependency cycle, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reports_missing_dependencies() {
+        let dir = packages_dir("missing", &[("a", &["b"])]);
+        match dependencies(&dir.join("a"), &GlobalConfig::default()) {
+            Err(Error::MissingDependencyError(package, dependency)) => {
+                assert_eq!((package.as_str(), dependency.as_str()), ("a", "b"))
+            }
+            result => panic!("expected a missing dependency, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn resolves_dependencies_in_the_packages_root() {
+        let dir = packages_dir(
+            "root",
+            &[("elsewhere/a", &["b"]), ("elsewhere/b", &[]), ("dots/b", &[])],
+        );
+        let global_config = GlobalConfig {
+            packages: Some(dir.join("dots").to_string_lossy().into_owned()),
+            ..GlobalConfig::default()
+        };
+
+        let dependencies = dependencies(&dir.join("elsewhere/a"), &global_config).unwrap();
+        assert_eq!(dependencies, [dir.join("dots/b")]);
+    }
+}
@@ -13,37 +13,145 @@ pub type Result<T> = result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     CommandError(String, String),
+    ConflictError(usize),
+    ConflictingPackageError(String, PathBuf),
+    DanglingLinkError(PathBuf, PathBuf),
+    DeclaredConflictError(PathBuf, String),
+    DependedOnError(String, String),
+    DependencyCycleError(Vec<String>),
+    DryRunError(usize),
     FileDoesNotExistError(PathBuf),
     FileExistsError(PathBuf),
     IgnoreError(ignore::Error),
     IoError(io::Error),
+    MissingDependencyError(String, String),
     NotDirectoryError(PathBuf),
-    NotSymlinkError(PathBuf),
+    OwnedFileError(PathBuf, PathBuf),
     PathError(StripPrefixError),
+    RollbackError(Box<Error>, Box<Error>),
+    TemplateError(PathBuf, String),
     TomlError(toml::de::Error),
+    TomlSerializeError(toml::ser::Error),
+    TypeMismatchError(PathBuf, PathBuf),
     VarError(env::VarError),
 }
 
+impl Error {
+    /// A stable, machine-readable name for the kind of error.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Error::CommandError(..) => "command",
+            Error::ConflictError(_) => "conflict",
+            Error::ConflictingPackageError(..) => "conflicting_package",
+            Error::DanglingLinkError(..) => "dangling_link",
+            Error::DeclaredConflictError(..) => "declared_conflict",
+            Error::DependedOnError(..) => "depended_on",
+            Error::DependencyCycleError(_) => "dependency_cycle",
+            Error::DryRunError(_) => "dry_run",
+            Error::FileDoesNotExistError(_) => "file_does_not_exist",
+            Error::FileExistsError(_) => "file_exists",
+            Error::IgnoreError(_) => "ignore",
+            Error::IoError(_) => "io",
+            Error::MissingDependencyError(..) => "missing_dependency",
+            Error::NotDirectoryError(_) => "not_directory",
+            Error::OwnedFileError(..) => "owned_file",
+            Error::PathError(_) => "path",
+            Error::RollbackError(..) => "rollback",
+            Error::TemplateError(..) => "template",
+            Error::TomlError(_) => "toml",
+            Error::TomlSerializeError(_) => "toml_serialize",
+            Error::TypeMismatchError(..) => "type_mismatch",
+            Error::VarError(_) => "var",
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::CommandError(ref command, ref message) => {
                 write!(fmt, "`{}` failed: {}", command, message)
             }
+            Error::ConflictError(conflict_count) => {
+                let noun = if conflict_count == 1 { "conflict" } else { "conflicts" };
+                write!(fmt, "found {} {}", conflict_count, noun)
+            }
+            Error::ConflictingPackageError(ref name, ref package) => {
+                write!(
+                    fmt,
+                    "conflicts with `{}`, provided by the installed package `{}`",
+                    name,
+                    package.display()
+                )
+            }
+            Error::DanglingLinkError(ref path, ref destination) => {
+                write!(
+                    fmt,
+                    "`{}` is a dangling link to `{}` (use --replace-dangling to replace it)",
+                    path.display(),
+                    destination.display()
+                )
+            }
+            Error::DeclaredConflictError(ref package, ref name) => {
+                write!(
+                    fmt,
+                    "the installed package `{}` declares a conflict with `{}`",
+                    package.display(),
+                    name
+                )
+            }
+            Error::DependedOnError(ref package, ref dependent) => {
+                write!(
+                    fmt,
+                    "`{}` is still needed by `{}` (use --force to uninstall it anyway)",
+                    package,
+                    dependent
+                )
+            }
+            Error::DependencyCycleError(ref cycle) => {
+                write!(fmt, "dependency cycle: {}", cycle.join(" -> "))
+            }
+            Error::DryRunError(conflict_count) => {
+                let noun = if conflict_count == 1 { "conflict" } else { "conflicts" };
+                write!(fmt, "dry run found {} {}", conflict_count, noun)
+            }
             Error::FileDoesNotExistError(ref path) => {
                 write!(fmt, "`{}` does not exist", path.display())
             }
             Error::FileExistsError(ref path) => write!(fmt, "`{}` already exists", path.display()),
             Error::IgnoreError(ref error) => fmt::Display::fmt(error, fmt),
             Error::IoError(ref error) => fmt::Display::fmt(error, fmt),
+            Error::MissingDependencyError(ref package, ref dependency) => {
+                write!(fmt, "`{}` depends on `{}`, which doesn't exist", package, dependency)
+            }
             Error::NotDirectoryError(ref path) => {
                 write!(fmt, "`{}` is not a directory", path.display())
             }
-            Error::NotSymlinkError(ref path) => {
-                write!(fmt, "`{}` is not a symlink", path.display())
+            Error::OwnedFileError(ref path, ref package) => {
+                write!(
+                    fmt,
+                    "`{}` belongs to the installed package `{}`",
+                    path.display(),
+                    package.display()
+                )
             }
             Error::PathError(ref error) => fmt::Display::fmt(error, fmt),
+            Error::RollbackError(ref error, ref rollback_error) => {
+                write!(fmt, "{} (rolling back also failed: {})", error, rollback_error)
+            }
+            Error::TemplateError(ref path, ref message) => {
+                write!(fmt, "can't render `{}`: {}", path.display(), message)
+            }
             Error::TomlError(ref error) => fmt::Display::fmt(error, fmt),
+            Error::TomlSerializeError(ref error) => fmt::Display::fmt(error, fmt),
+            Error::TypeMismatchError(ref path, ref source) => {
+                write!(
+                    fmt,
+                    "can't adopt `{}` in place of `{}`, which isn't the same kind of file",
+                    path.display(),
+                    source.display()
+                )
+            }
             Error::VarError(ref error) => fmt::Display::fmt(error, fmt),
         }
     }
@@ -79,6 +187,12 @@ impl From<toml::de::Error> for Error {
     }
 }
 
+impl From<toml::ser::Error> for Error {
+    fn from(error: toml::ser::Error) -> Error {
+        Error::TomlSerializeError(error)
+    }
+}
+
 impl From<env::VarError> for Error {
     fn from(error: env::VarError) -> Error {
         Error::VarError(error)
@@ -0,0 +1,388 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::rc::Rc;
+
+use super::{Action, BackupRecord, Error, Event, LinkRecord, Mode, Operation, Options, Plan,
+            Reporter, Result, State, Transaction};
+use super::{is_real_dir, relative_path, template};
+
+/// Carries out `Plan`s, reporting each operation as it goes.
+///
+/// In a dry run, operations and conflicts are only reported.
+pub struct Executor {
+    pub dry_run: bool,
+    reporter: Rc<dyn Reporter>,
+}
+
+impl Executor {
+    pub fn new(options: &Options) -> Executor {
+        Executor {
+            dry_run: options.dry_run,
+            reporter: options.reporter.clone(),
+        }
+    }
+
+    pub fn execute(&self, plan: &Plan) -> Result<()> {
+        self.reporter.report(&Event::PackageStarted {
+            action: plan.action,
+            package: plan.package.clone(),
+            target: plan.target.clone(),
+            dry_run: self.dry_run,
+        });
+
+        for conflict in &plan.conflicts {
+            self.reporter.report(&Event::Conflict { conflict: conflict.clone() });
+        }
+
+        if self.dry_run {
+            for operation in &plan.operations {
+                self.reporter.report(&operation_event(operation));
+            }
+
+            if !plan.is_ok() {
+                return Err(Error::DryRunError(plan.conflicts.len()));
+            }
+        } else {
+            if !plan.is_ok() {
+                return Err(Error::ConflictError(plan.conflicts.len()));
+            }
+
+            self.transaction(plan)?;
+        }
+
+        self.reporter.report(&Event::PackageFinished {
+            action: plan.action,
+            package: plan.package.clone(),
+        });
+        Ok(())
+    }
+
+    /// Executes every operation in a transaction, saving the updated state if
+    /// they all succeed and undoing all of their changes if any fail.
+    fn transaction(&self, plan: &Plan) -> Result<()> {
+        let mut state = State::load()?;
+        let mut transaction = Transaction::new();
+
+        let result = self.execute_operations(plan, &mut state, &mut transaction)
+            .and_then(|()| state.save());
+
+        if let Err(error) = result {
+            if let Err(rollback_error) = transaction.rollback() {
+                return Err(Error::RollbackError(Box::new(error), Box::new(rollback_error)));
+            }
+
+            self.reporter.report(&Event::RolledBack { package: plan.package.clone() });
+            return Err(error);
+        }
+
+        transaction.commit()?;
+
+        if plan.action == Action::RestoreBackup {
+            remove_empty_backup_dirs(plan);
+        }
+
+        Ok(())
+    }
+
+    fn execute_operations(
+        &self,
+        plan: &Plan,
+        state: &mut State,
+        transaction: &mut Transaction,
+    ) -> Result<()> {
+        match plan.action {
+            Action::Install | Action::Adopt => {
+                state.record_mut(&plan.package, &plan.target).target = plan.target.clone();
+            }
+            Action::Uninstall | Action::Reinstall | Action::RestoreBackup => {
+                state.record_mut(&plan.package, &plan.target).hooks.clear();
+            }
+            Action::Prune => {}
+        }
+
+        for operation in &plan.operations {
+            self.execute_operation(plan, operation, state, transaction)?;
+        }
+
+        match plan.action {
+            Action::Uninstall | Action::RestoreBackup => {
+                let is_empty = state.record(&plan.package).is_some_and(|record| {
+                    record.links.is_empty() && record.backups.is_empty()
+                });
+
+                if is_empty {
+                    state.remove_record(&plan.package);
+                }
+            }
+            Action::Reinstall => {
+                state.record_mut(&plan.package, &plan.target).target = plan.target.clone();
+            }
+            Action::Install | Action::Adopt | Action::Prune => {}
+        }
+
+        Ok(())
+    }
+
+    fn execute_operation(
+        &self,
+        plan: &Plan,
+        operation: &Operation,
+        state: &mut State,
+        transaction: &mut Transaction,
+    ) -> Result<()> {
+        match *operation {
+            Operation::CreateSymlink {
+                ref source,
+                ref target,
+                relative,
+            } => {
+                transaction.symlink(&link_source(source, target, relative)?, target)?;
+                state.add_link(LinkRecord {
+                    source: source.clone(),
+                    target: target.clone(),
+                    mode: Mode::Symlink,
+                    checksum: None,
+                });
+            }
+            Operation::CopyFile { ref source, ref target } => {
+                transaction.copy_file(source, target)?;
+                state.add_link(LinkRecord {
+                    source: source.clone(),
+                    target: target.clone(),
+                    mode: Mode::Copy,
+                    checksum: Some(template::checksum(&fs::read(target)?)),
+                });
+            }
+            Operation::HardLink { ref source, ref target } => {
+                transaction.hard_link(source, target)?;
+                state.add_link(LinkRecord {
+                    source: source.clone(),
+                    target: target.clone(),
+                    mode: Mode::Hardlink,
+                    checksum: None,
+                });
+            }
+            Operation::CreateDir { ref path } => {
+                transaction.create_dir(path)?;
+                state.record_mut(&plan.package, &plan.target).add_dir(path);
+            }
+            Operation::RemoveDir { ref path } => {
+                transaction.remove_dir(path);
+                state.remove_dir(path);
+            }
+            Operation::RenderTemplate {
+                ref source,
+                ref target,
+                ref contents,
+            } => {
+                transaction.write_file(target, contents.as_bytes())?;
+                fs::set_permissions(target, fs::metadata(source)?.permissions())?;
+                state.add_link(LinkRecord {
+                    source: source.clone(),
+                    target: target.clone(),
+                    mode: Mode::Copy,
+                    checksum: Some(template::checksum(contents.as_bytes())),
+                });
+            }
+            Operation::RemovePath { ref path } => {
+                transaction.remove(path)?;
+                state.remove_link(path);
+            }
+            Operation::Backup { ref path, ref backup } => {
+                transaction.keep(path, backup)?;
+                state.record_mut(&plan.package, &plan.target).backups.push(BackupRecord {
+                    original: path.clone(),
+                    backup: backup.clone(),
+                });
+            }
+            Operation::Adopt { ref target, ref source } => {
+                if is_real_dir(target) != is_real_dir(source) {
+                    return Err(Error::TypeMismatchError(target.clone(), source.clone()));
+                }
+
+                transaction.remove(source)?;
+                transaction.rename(target, source)?;
+            }
+            Operation::Restore { ref backup, ref original } => {
+                transaction.rename(backup, original)?;
+                state.record_mut(&plan.package, &plan.target).backups.retain(|record| {
+                    record.backup != *backup
+                });
+            }
+            Operation::Unfold {
+                ref path,
+                relative,
+                ref links,
+                ..
+            } => {
+                transaction.remove(path)?;
+                transaction.create_dir(path)?;
+
+                for link in links {
+                    let link_source = link_source(&link.source, &link.target, relative)?;
+                    transaction.symlink(&link_source, &link.target)?;
+                }
+
+                state.unfold_link(path, links.clone());
+                state.record_mut(&plan.package, &plan.target).add_dir(path);
+            }
+            Operation::Fold {
+                ref path,
+                ref source,
+                relative,
+            } => {
+                for entry_result in fs::read_dir(path)? {
+                    let entry_path = entry_result?.path();
+                    if !transaction.is_backup(&entry_path) {
+                        transaction.remove(&entry_path)?;
+                    }
+                }
+
+                transaction.remove(path)?;
+                transaction.symlink(&link_source(source, path, relative)?, path)?;
+                state.remove_dir(path);
+                state.fold_links(LinkRecord {
+                    source: source.clone(),
+                    target: path.clone(),
+                    mode: Mode::Symlink,
+                    checksum: None,
+                });
+            }
+            Operation::Unchanged {
+                ref source,
+                ref target,
+                mode,
+            } => {
+                let checksum = match mode {
+                    Mode::Copy => Some(template::checksum(&fs::read(target)?)),
+                    Mode::Symlink | Mode::Hardlink => None,
+                };
+
+                state.add_link(LinkRecord {
+                    source: source.clone(),
+                    target: target.clone(),
+                    mode,
+                    checksum,
+                });
+            }
+            Operation::Forget { ref path, .. } => state.remove_link(path),
+            Operation::Skip { .. } => {}
+            Operation::RunHook {
+                ref name,
+                ref command,
+                ref script,
+                ref dir,
+                ref env,
+            } => {
+                self.reporter.report(&operation_event(operation));
+
+                if let Some(ref command_str) = *command {
+                    self.run_hook(
+                        name,
+                        command_str,
+                        Command::new("sh").arg("-c").arg(command_str).current_dir(dir).envs(env),
+                    )?;
+                } else if let Some(ref script_path) = *script {
+                    let script_name = script_path.strip_prefix(dir).unwrap_or(script_path);
+                    let script_str = script_name.to_string_lossy();
+                    self.run_hook(
+                        name,
+                        &script_str,
+                        Command::new(script_path).current_dir(dir).envs(env),
+                    )?;
+                }
+
+                if name == "pre_install" || name == "post_install" {
+                    state.record_mut(&plan.package, &plan.target).add_hook(name);
+                }
+
+                return Ok(());
+            }
+        }
+
+        self.reporter.report(&operation_event(operation));
+        Ok(())
+    }
+
+    fn run_hook(&self, name: &str, command_str: &str, command: &mut Command) -> Result<()> {
+        let output = command.output()?;
+        self.reporter.report(&hook_finished_event(name, &output));
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            let message = String::from_utf8_lossy(&output.stderr).into_owned();
+            Err(Error::CommandError(command_str.to_owned(), message))
+        }
+    }
+}
+
+fn operation_event(operation: &Operation) -> Event {
+    match operation.clone() {
+        Operation::CreateSymlink { source, target, .. } => Event::LinkCreated { source, target },
+        Operation::CopyFile { source, target } => Event::FileCopied { source, target },
+        Operation::HardLink { source, target } => Event::HardLinkCreated { source, target },
+        Operation::CreateDir { path } => Event::DirCreated { path },
+        Operation::RemoveDir { path } => Event::DirRemoved { path },
+        Operation::RenderTemplate { source, target, .. } => {
+            Event::TemplateRendered { source, target }
+        }
+        Operation::RemovePath { path } => Event::LinkRemoved { target: path },
+        Operation::Backup { path, backup } => Event::BackedUp { path, backup },
+        Operation::Adopt { target, source } => Event::Adopted { target, source },
+        Operation::Restore { backup, original } => Event::Restored { backup, original },
+        Operation::Unfold { path, source, .. } => Event::Unfolded { path, source },
+        Operation::Fold { path, source, .. } => Event::Folded { path, source },
+        Operation::Unchanged { target, .. } => Event::LinkUnchanged { target },
+        Operation::Forget { path, reason } | Operation::Skip { path, reason } => {
+            Event::LinkSkipped { target: path, reason }
+        }
+        Operation::RunHook {
+            name,
+            command,
+            script,
+            dir,
+            env,
+        } => Event::HookStarted {
+            name,
+            command,
+            script,
+            dir,
+            env,
+        },
+    }
+}
+
+/// What a symlink at `target` to `source` should contain, relative to the
+/// real location of its parent directory if `relative` is set.
+fn link_source(source: &Path, target: &Path, relative: bool) -> Result<PathBuf> {
+    match target.parent() {
+        Some(parent) if relative => Ok(relative_path(&fs::canonicalize(parent)?, source)),
+        _ => Ok(source.to_owned()),
+    }
+}
+
+fn hook_finished_event(name: &str, output: &Output) -> Event {
+    Event::HookFinished {
+        name: name.to_owned(),
+        success: output.status.success(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }
+}
+
+/// Cleans up the directories that held backups which have been restored, up
+/// to and including the package's own backup directory.
+fn remove_empty_backup_dirs(plan: &Plan) {
+    for operation in &plan.operations {
+        if let Operation::Restore { ref backup, ref original } = *operation {
+            let depth = original.strip_prefix(&plan.target).map_or(0, |path| path.components().count());
+            for ancestor in backup.ancestors().skip(1).take(depth + 1) {
+                if fs::remove_dir(ancestor).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Component, Path, PathBuf};
+
+use shellexpand;
+use toml;
+
+use super::{Config, Result, DEFAULT_TARGET};
+
+const DEFAULT_CONFIG_HOME: &str = "~/.config";
+const CONFIG_DIRNAME: &str = "scrubjay";
+const CONFIG_FILENAME: &str = "config.toml";
+
+/// Per-user settings that apply to every package, read from
+/// `$XDG_CONFIG_HOME/scrubjay/config.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GlobalConfig {
+    /// The directory packages can be named relative to, e.g. a dotfiles
+    /// checkout.
+    pub packages: Option<String>,
+    pub verbose: Option<bool>,
+    /// Whether files replaced with `--force` are backed up first.
+    pub backup: Option<bool>,
+    pub target: Option<String>,
+    pub backup_dir: Option<String>,
+    pub relative: Option<bool>,
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Custom variables for conditional file names.
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+}
+
+impl GlobalConfig {
+    pub fn path() -> Result<PathBuf> {
+        let config_home = match env::var("XDG_CONFIG_HOME") {
+            Ok(ref path_str) if !path_str.is_empty() => PathBuf::from(path_str),
+            _ => PathBuf::from(shellexpand::full(DEFAULT_CONFIG_HOME)?.into_owned()),
+        };
+
+        Ok(config_home.join(CONFIG_DIRNAME).join(CONFIG_FILENAME))
+    }
+
+    pub fn load() -> Result<GlobalConfig> {
+        let mut file = match File::open(GlobalConfig::path()?) {
+            Ok(file) => file,
+            Err(_) => return Ok(GlobalConfig::default()),
+        };
+
+        let mut toml_str = String::new();
+        file.read_to_string(&mut toml_str)?;
+        Ok(toml::from_str(&toml_str)?)
+    }
+
+    pub fn packages_root(&self) -> Result<Option<PathBuf>> {
+        match self.packages {
+            Some(ref path_str) => Ok(Some(PathBuf::from(shellexpand::full(path_str)?.into_owned()))),
+            None => Ok(None),
+        }
+    }
+
+    /// The directory packages are installed to unless they say otherwise.
+    pub fn target_root(&self) -> Result<PathBuf> {
+        let path_str = self.target.as_deref().unwrap_or(DEFAULT_TARGET);
+        Ok(PathBuf::from(shellexpand::full(path_str)?.into_owned()))
+    }
+
+    /// Resolves a package name given on the command line, looking for bare
+    /// names like `vim` in the packages root before the current directory.
+    pub fn package_path(&self, package_name: &str) -> Result<PathBuf> {
+        let path = Path::new(package_name);
+        let mut components = path.components();
+        let is_bare_name = matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        );
+
+        if is_bare_name {
+            if let Some(packages_root) = self.packages_root()? {
+                let package_path = packages_root.join(path);
+                if package_path.is_dir() {
+                    return Ok(package_path);
+                }
+            }
+        }
+
+        Ok(path.to_owned())
+    }
+
+    /// The settings every package's own config is merged with.
+    pub fn package_defaults(&self) -> Config {
+        Config {
+            target: self.target.clone(),
+            backup_dir: self.backup_dir.clone(),
+            mode: None,
+            modes: BTreeMap::new(),
+            relative: self.relative,
+            ignore: self.ignore.clone(),
+            depends: Vec::new(),
+            conflicts: Vec::new(),
+            provides: Vec::new(),
+            vars: self.vars.clone(),
+            hooks: None,
+        }
+    }
+}
@@ -1,18 +1,98 @@
+use std::fs;
 use std::path::PathBuf;
 
 use ignore::{DirEntry, Walk};
 
 use super::{Error, Result};
-use super::Package;
+use super::{conditions, is_within, link_destination, template, Package};
 
 pub struct Link {
     pub entry: DirEntry,
     pub target_path: PathBuf,
 }
 
+/// The state of a link's target path relative to the package entry it
+/// should point to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "destination", rename_all = "snake_case")]
+pub enum LinkStatus {
+    Linked,
+    /// A file copied from the package that is up to date.
+    Copied,
+    /// A template rendered to a file that is up to date.
+    Rendered,
+    /// A copied file or rendered template that differs from its source, e.g.
+    /// because it was edited by hand.
+    Modified,
+    Missing,
+    Elsewhere(PathBuf),
+    Dangling(PathBuf),
+    File,
+    Directory,
+}
+
+/// How much of a package is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallStatus {
+    Installed,
+    PartiallyInstalled,
+    NotInstalled,
+}
+
+impl InstallStatus {
+    pub fn from_counts(linked_count: usize, total_count: usize) -> InstallStatus {
+        if linked_count == 0 {
+            InstallStatus::NotInstalled
+        } else if linked_count == total_count {
+            InstallStatus::Installed
+        } else {
+            InstallStatus::PartiallyInstalled
+        }
+    }
+}
+
+impl LinkStatus {
+    pub fn is_ok(&self) -> bool {
+        matches!(*self, LinkStatus::Linked | LinkStatus::Copied | LinkStatus::Rendered)
+    }
+}
+
+impl Link {
+    pub fn is_dir(&self) -> bool {
+        self.entry.file_type().is_some_and(|file_type| file_type.is_dir())
+    }
+
+    pub fn is_template(&self) -> bool {
+        !self.is_dir() && template::is_template(self.entry.path())
+    }
+
+    pub fn status(&self) -> Result<LinkStatus> {
+        let metadata = match fs::symlink_metadata(&self.target_path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(LinkStatus::Missing),
+        };
+
+        let destination = match link_destination(&self.target_path)? {
+            Some(destination) => destination,
+            None if metadata.is_dir() => return Ok(LinkStatus::Directory),
+            None => return Ok(LinkStatus::File),
+        };
+
+        if destination == self.entry.path() {
+            Ok(LinkStatus::Linked)
+        } else if destination.exists() {
+            Ok(LinkStatus::Elsewhere(destination))
+        } else {
+            Ok(LinkStatus::Dangling(destination))
+        }
+    }
+}
+
 pub struct Links<'a> {
     package: &'a Package<'a>,
     walker: Walk,
+    skipped_dir: Option<PathBuf>,
 }
 
 impl<'a> Links<'a> {
@@ -21,8 +101,9 @@ impl<'a> Links<'a> {
         walker.next().unwrap()?;
 
         Ok(Links {
-            package: package,
-            walker: walker,
+            package,
+            walker,
+            skipped_dir: None,
         })
     }
 }
@@ -31,22 +112,34 @@ impl<'a> Iterator for Links<'a> {
     type Item = Result<Link>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.walker.next() {
-            Some(Ok(entry)) => {
-                if entry.path() == &self.package.path {
-                    self.next()
-                } else {
-                    let link_result = self.package.target_path(entry.path()).map(|target_path| {
-                        Link {
-                            entry: entry,
-                            target_path: target_path,
-                        }
-                    });
-                    Some(link_result)
+        loop {
+            let entry = match self.walker.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(error)) => return Some(Err(Error::IgnoreError(error))),
+                None => return None,
+            };
+
+            if entry.path() == self.package.path || is_within(entry.path(), &self.skipped_dir) {
+                continue;
+            }
+
+            // Variants of a conditional entry that don't match this machine
+            // are skipped, along with everything in them
+            if !conditions::is_best_match(entry.path(), &self.package.facts) {
+                if entry.file_type().is_some_and(|file_type| file_type.is_dir()) {
+                    self.skipped_dir = Some(entry.path().to_owned());
                 }
+
+                continue;
             }
-            Some(Err(error)) => Some(Err(Error::IgnoreError(error))),
-            None => None,
+
+            let link_result = self.package.target_path(entry.path()).map(|target_path| {
+                Link {
+                    entry,
+                    target_path,
+                }
+            });
+            return Some(link_result);
         }
     }
 }
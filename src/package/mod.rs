@@ -1,44 +1,76 @@
+mod conditions;
 #[macro_use]
 mod config;
+mod depends;
 mod error;
+mod executor;
+mod global_config;
 mod links;
+mod options;
+mod plan;
+mod prune;
+mod report;
+mod state;
+mod template;
+#[cfg(test)]
+mod testing;
+mod transaction;
 
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
 use std::fs;
-use std::os::unix::fs::symlink;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::slice;
 
-use colored::*;
 use ignore::{Walk, WalkBuilder};
 use ignore::overrides::{Override, OverrideBuilder};
 use shellexpand;
 
-pub use self::config::{Config, Hook};
+pub use self::conditions::Facts;
+pub use self::config::{Config, Hook, Mode};
+pub use self::depends::{dependencies, install_order, uninstall_order};
 pub use self::error::{Error, Result};
-pub use self::links::{Link, Links};
+pub use self::executor::Executor;
+pub use self::global_config::GlobalConfig;
+pub use self::links::{InstallStatus, Link, LinkStatus, Links};
+pub use self::options::Options;
+pub use self::plan::{Action, Conflict, Operation, Plan};
+pub use self::prune::plan_prune;
+pub use self::report::{Event, NullReporter, Reporter};
+pub use self::state::{BackupRecord, LinkRecord, Record, State};
+pub use self::transaction::Transaction;
 
-use super::Config as ProgramConfig;
-
-const DEFAULT_TARGET: &'static str = "~";
-const IGNORE_FILENAME: &'static str = ".ignore";
-const INDENT: &'static str = "‣ ";
+const DEFAULT_TARGET: &str = "~";
+const IGNORE_FILENAME: &str = ".ignore";
 
 pub struct Package<'a> {
     path: PathBuf,
     config: Config,
-    program_config: &'a ProgramConfig,
+    facts: Facts,
+    mode_overrides: Vec<(Override, Mode)>,
+    options: &'a Options,
 }
 
-macro_rules! maybe_run_hook {
-    ($self_expr:expr, $hook_expr:expr, $field:ident) => {
-        if let Some(ref hook) = hook_field!($hook_expr, $field) {
-            $self_expr.run_hook(hook)?;
+/// Plans running the hook for `$field`, if the package has one.
+macro_rules! plan_hook {
+    ($self_expr:expr, $plan_expr:expr, $field:ident) => {
+        if let Some(ref hook) = hook_field!($self_expr.config.hooks, $field) {
+            let env = $self_expr.hook_env(&$plan_expr, stringify!($field), hook);
+            $plan_expr.operations.push(Operation::RunHook {
+                name: stringify!($field).to_owned(),
+                command: hook.command.clone(),
+                script: hook.script.as_ref().map(|script_name| $self_expr.path.join(script_name)),
+                dir: $self_expr.path.clone(),
+                env,
+            });
         }
-    }
+    };
 }
 
 impl<'a> Package<'a> {
-    pub fn new(relative_path: &Path, program_config: &'a ProgramConfig) -> Result<Package<'a>> {
+    pub fn new(relative_path: &Path, options: &'a Options) -> Result<Package<'a>> {
         if !relative_path.exists() {
             return Err(Error::FileDoesNotExistError(relative_path.into()));
         }
@@ -48,264 +80,932 @@ impl<'a> Package<'a> {
             return Err(Error::NotDirectoryError(path));
         }
 
-        let config = Config::from_dir(&path)?;
+        let config = Config::from_dir(&path)?.merge(&options.defaults);
+        let facts = Facts::current(&config.vars);
+        let mode_overrides = build_mode_overrides(&path, &config)?;
 
         Ok(Package {
-            path: path,
-            config: config,
-            program_config: program_config,
+            path,
+            config,
+            facts,
+            mode_overrides,
+            options,
         })
     }
 
-    pub fn install(&self) -> Result<()> {
-        if self.config.target.is_some() {
-            let target_root = self.target_root()?;
-            println!(
-                "{} {} {} {}{}",
-                "Installing".green(),
-                self.path_str(&self.path),
-                "to".green(),
-                self.path_str(&target_root),
-                "...".green(),
-            );
-        } else {
-            println!(
-                "{} {}{}",
-                "Installing".green(),
-                self.path_str(&self.path),
-                "...".green(),
-            );
+    /// Works out everything `action` would do to the package's target
+    /// directory, without changing anything.
+    pub fn plan(&self, action: Action) -> Result<Plan> {
+        let state = State::load()?;
+        let recorded_target = match action {
+            Action::Uninstall | Action::RestoreBackup if self.options.target.is_none() => {
+                state.record(&self.path).map(|record| record.target.clone())
+            }
+            _ => None,
+        };
+
+        let target = match recorded_target {
+            Some(target) => target,
+            None => self.target_root()?,
+        };
+
+        let mut plan = Plan::new(action, &self.path, &target);
+
+        match action {
+            Action::Install => self.plan_install(&mut plan, &state, false)?,
+            Action::Adopt => self.plan_install(&mut plan, &state, true)?,
+            Action::Uninstall => self.plan_uninstall(&mut plan, &state, true)?,
+            Action::Reinstall => {
+                self.plan_uninstall(&mut plan, &state, false)?;
+                self.plan_install(&mut plan, &state, false)?;
+            }
+            Action::RestoreBackup => {
+                self.plan_uninstall(&mut plan, &state, true)?;
+                self.plan_restore(&mut plan, &state)?;
+            }
+            Action::Prune => prune::plan_prune(&mut plan, slice::from_ref(&self.path), &state)?,
         }
 
-        maybe_run_hook!(self, self.config.hooks, pre_install);
+        Ok(plan)
+    }
+
+    /// Checks each of the package's links, skipping the contents of any
+    /// directory that is linked (or missing) as a whole.
+    pub fn link_statuses(&self) -> Result<Vec<(PathBuf, LinkStatus)>> {
+        let mut statuses = Vec::new();
+        let mut checked_dir: Option<PathBuf> = None;
 
         for link_result in self.links()? {
             let link = link_result?;
-            self.create_link(&link)?;
+            if is_within(link.entry.path(), &checked_dir) {
+                continue;
+            }
+
+            let status = self.link_status(&link)?;
+
+            if link.is_dir() {
+                if status == LinkStatus::Directory {
+                    continue;
+                }
+
+                checked_dir = Some(link.entry.path().to_owned());
+            }
+
+            statuses.push((link.target_path, status));
         }
 
-        maybe_run_hook!(self, self.config.hooks, post_install);
+        Ok(statuses)
+    }
 
-        println!(
-            "{} {}",
-            "Installed".green(),
-            self.path_str(&self.path),
-        );
-        Ok(())
+    fn link_status(&self, link: &Link) -> Result<LinkStatus> {
+        if link.is_template() {
+            self.template_status(link)
+        } else if !self.mode(link).is_symlink() {
+            self.file_status(link)
+        } else {
+            link.status()
+        }
+    }
+
+    /// The status of `link` once it's installed.
+    fn installed_status(&self, link: &Link) -> LinkStatus {
+        if link.is_template() {
+            return LinkStatus::Rendered;
+        }
+
+        match self.mode(link) {
+            Mode::Symlink | Mode::Hardlink => LinkStatus::Linked,
+            Mode::Copy => LinkStatus::Copied,
+        }
     }
 
-    pub fn uninstall(&self) -> Result<()> {
-        if self.config.target.is_some() {
-            let target_root = self.target_root()?;
-            println!(
-                "{} {} {} {}{}",
-                "Uninstalling".green(),
-                self.path_str(&self.path),
-                "from".green(),
-                self.path_str(&target_root),
-                "...".green(),
-            );
+    /// Compares a rendered template with what rendering it now would
+    /// produce.
+    fn template_status(&self, link: &Link) -> Result<LinkStatus> {
+        match fs::symlink_metadata(&link.target_path) {
+            Ok(ref metadata) if metadata.is_file() => {}
+            _ => return link.status(),
+        }
+
+        let contents = fs::read(&link.target_path)?;
+        if contents == self.render(link)?.as_bytes() {
+            Ok(LinkStatus::Rendered)
         } else {
-            println!(
-                "{} {}{}",
-                "Uninstalling".green(),
-                self.path_str(&self.path),
-                "...".green(),
-            );
+            Ok(LinkStatus::Modified)
         }
+    }
 
-        maybe_run_hook!(self, self.config.hooks, pre_uninstall);
+    /// Compares a copied or hard-linked file with its source.
+    fn file_status(&self, link: &Link) -> Result<LinkStatus> {
+        match fs::symlink_metadata(&link.target_path) {
+            Ok(ref metadata) if metadata.is_file() => {}
+            _ => return link.status(),
+        }
+
+        if is_same_file(&link.target_path, link.entry.path()) {
+            Ok(LinkStatus::Linked)
+        } else if fs::read(&link.target_path)? == fs::read(link.entry.path())? {
+            Ok(LinkStatus::Copied)
+        } else {
+            Ok(LinkStatus::Modified)
+        }
+    }
+
+    pub fn install_status(&self) -> Result<InstallStatus> {
+        let statuses = self.link_statuses()?;
+        let linked_count = statuses.iter().filter(|(_, status)| status.is_ok()).count();
+        Ok(InstallStatus::from_counts(linked_count, statuses.len()))
+    }
+
+    /// Reports the status of the package's links, returning whether all of
+    /// them are installed correctly.
+    pub fn status(&self) -> Result<bool> {
+        let reporter = &self.options.reporter;
+        let statuses = self.link_statuses()?;
+        let total_count = statuses.len();
+        let mut linked_count = 0;
+
+        for (target, status) in statuses {
+            if status.is_ok() {
+                linked_count += 1;
+            }
+
+            reporter.report(&Event::LinkChecked { target, status });
+        }
+
+        reporter.report(&Event::PackageChecked {
+            package: self.path.clone(),
+            linked: linked_count,
+            total: total_count,
+        });
+        Ok(linked_count == total_count)
+    }
+
+    /// Plans the package's links, moving any files in the way into the
+    /// package if `adopt` is set.
+    fn plan_install(&self, plan: &mut Plan, state: &State, adopt: bool) -> Result<()> {
+        self.plan_package_conflicts(plan, state);
+
+        plan_hook!(self, plan, pre_install);
+
+        // Creates the target directory and any of its parents that are missing,
+        // outermost first
+        let missing_dirs: Vec<PathBuf> = plan.target
+            .ancestors()
+            .take_while(|dir| !plan.exists(dir))
+            .map(Path::to_owned)
+            .collect();
+
+        for dir in missing_dirs.into_iter().rev() {
+            plan.operations.push(Operation::CreateDir { path: dir });
+        }
+
+        let mut linked_dir: Option<PathBuf> = None;
 
         for link_result in self.links()? {
             let link = link_result?;
-            self.remove_link(&link)?;
+            if is_within(link.entry.path(), &linked_dir) {
+                continue;
+            }
+
+            if self.plan_dangling_link(&link.target_path, plan, state)? {
+                continue;
+            }
+
+            if link.is_dir() && self.plan_target_dir(&link, plan, state)? {
+                continue;
+            }
+
+            let is_mismatched = adopt && self.plan_adopt(&link, plan)?;
+            if !is_mismatched {
+                self.plan_link(&link, plan, state)?;
+            }
+
+            if link.is_dir() {
+                linked_dir = Some(link.entry.path().to_owned());
+            }
         }
 
-        maybe_run_hook!(self, self.config.hooks, post_uninstall);
+        plan_hook!(self, plan, post_install);
 
-        println!(
-            "{} {}",
-            "Uninstalled".green(),
-            self.path_str(&self.path),
-        );
         Ok(())
     }
 
-    pub fn reinstall(&self) -> Result<()> {
-        if self.config.target.is_some() {
-            let target_root = self.target_root()?;
-            println!(
-                "{} {} {} {}{}",
-                "Reinstalling".green(),
-                self.path_str(&self.path),
-                "to".green(),
-                self.path_str(&target_root),
-                "...".green(),
-            );
-        } else {
-            println!(
-                "{} {}{}",
-                "Reinstalling".green(),
-                self.path_str(&self.path),
-                "...".green(),
-            );
+    /// The environment variables a hook runs with, describing the package and
+    /// action along with the hook's own `env`.
+    fn hook_env(&self, plan: &Plan, hook_name: &str, hook: &Hook) -> BTreeMap<String, String> {
+        let flag = |value: bool| if value { "1" } else { "0" }.to_owned();
+
+        let mut env = BTreeMap::new();
+        env.insert("SJ_PACKAGE_NAME".to_owned(), self.name());
+        env.insert("SJ_PACKAGE_DIR".to_owned(), self.path.to_string_lossy().into_owned());
+        env.insert("SJ_TARGET_DIR".to_owned(), plan.target.to_string_lossy().into_owned());
+        env.insert("SJ_ACTION".to_owned(), plan.action.name().to_owned());
+        env.insert("SJ_DRY_RUN".to_owned(), flag(self.options.dry_run));
+        env.insert("SJ_VERBOSE".to_owned(), flag(self.options.verbose));
+        env.insert("SJ_HOOK".to_owned(), hook_name.to_owned());
+
+        for (name, value) in &hook.env {
+            env.insert(name.clone(), value.clone());
         }
 
-        maybe_run_hook!(self, self.config.hooks, pre_uninstall);
+        env
+    }
 
-        for link_result in self.links()? {
-            let link = link_result?;
-            self.remove_link(&link)?;
+    /// Checks this package's `conflicts` against the names and `provides` of
+    /// every installed package, and vice versa.
+    fn plan_package_conflicts(&self, plan: &mut Plan, state: &State) {
+        let mut names = self.config.provides.clone();
+        names.push(self.name());
+
+        for record in &state.packages {
+            if record.path == self.path {
+                continue;
+            }
+
+            let other_config = Config::from_dir(&record.path).unwrap_or_default();
+            let mut other_names = other_config.provides.clone();
+            if let Some(file_name) = record.path.file_name() {
+                other_names.push(file_name.to_string_lossy().into_owned());
+            }
+
+            let conflicting_name =
+                self.config.conflicts.iter().find(|name| other_names.contains(name));
+            let declared_name = other_config.conflicts.iter().find(|name| names.contains(name));
+
+            if let Some(name) = conflicting_name {
+                plan.conflicts.push(Conflict::ConflictingPackage {
+                    name: name.clone(),
+                    package: record.path.clone(),
+                });
+            } else if let Some(name) = declared_name {
+                plan.conflicts.push(Conflict::DeclaredConflict {
+                    package: record.path.clone(),
+                    name: name.clone(),
+                });
+            }
         }
+    }
 
-        maybe_run_hook!(self, self.config.hooks, post_uninstall);
+    /// Plans removing the links recorded for this package when it was
+    /// installed, falling back to the links implied by its current contents
+    /// if it was installed before Scrubjay kept track.
+    ///
+    /// Unless the package is about to be installed again, the directories it
+    /// created are removed if they're left empty, and any directories shared
+    /// with other packages that Scrubjay created are folded.
+    fn plan_uninstall(&self, plan: &mut Plan, state: &State, fold: bool) -> Result<()> {
+        plan_hook!(self, plan, pre_uninstall);
 
-        maybe_run_hook!(self, self.config.hooks, pre_install);
+        let mut target_dirs = match state.record(&self.path) {
+            Some(record) => self.plan_remove_recorded_links(record, plan)?,
+            None => self.plan_remove_links(plan)?,
+        };
 
-        for link_result in self.links()? {
-            let link = link_result?;
-            self.create_link(&link)?;
+        // Cleans up links to files that have since been removed from the
+        // package, wherever its links were
+        target_dirs.insert(plan.target.clone());
+        for target_dir in &target_dirs {
+            for entry_result in fs::read_dir(target_dir).into_iter().flatten() {
+                let entry_path = entry_result?.path();
+                if !plan.is_removed(&entry_path) &&
+                    prune::is_dangling_link(&entry_path, slice::from_ref(&self.path))?
+                {
+                    plan.operations.push(Operation::RemovePath { path: entry_path });
+                }
+            }
         }
 
-        maybe_run_hook!(self, self.config.hooks, post_install);
+        if fold {
+            if let Some(record) = state.record(&self.path) {
+                plan_remove_empty_dirs(&record.dirs, plan)?;
+            }
+
+            // Only directories Scrubjay created or unfolded are folded, never
+            // ones that were already there
+            for target_dir in target_dirs.iter().rev() {
+                if *target_dir != plan.target && state.is_recorded_dir(target_dir) {
+                    self.plan_fold(target_dir, plan, state)?;
+                }
+            }
+        }
+
+        plan_hook!(self, plan, post_uninstall);
 
-        println!(
-            "{} {}",
-            "Reinstalled".green(),
-            self.path_str(&self.path),
-        );
         Ok(())
     }
 
-    fn create_link(&self, link: &Link) -> Result<()> {
-        if !self.program_config.dry_run {
-            if link.target_path.exists() {
-                if self.program_config.force {
-                    remove_path(&link.target_path)?;
-                } else {
-                    return Err(Error::FileExistsError(link.target_path.clone()));
+    /// Returns the directories the removed links were in, parents first.
+    fn plan_remove_recorded_links(
+        &self,
+        record: &Record,
+        plan: &mut Plan,
+    ) -> Result<BTreeSet<PathBuf>> {
+        let mut target_dirs = BTreeSet::new();
+
+        for link in record.links.iter().rev() {
+            match link.mode {
+                Mode::Symlink => self.plan_remove_link(&link.target, &link.source, plan)?,
+                Mode::Copy | Mode::Hardlink => {
+                    // Copies are left alone unless they match either what was
+                    // installed or the current contents of their source
+                    let mut checksums: Vec<String> = link.checksum.iter().cloned().collect();
+                    if !template::is_template(&link.source) {
+                        checksums.extend(fs::read(&link.source).ok().map(|contents| {
+                            template::checksum(&contents)
+                        }));
+                    }
+
+                    self.plan_remove_file(&link.target, &link.source, &checksums, plan)?;
                 }
             }
 
-            let source_path = link.entry.path();
-            symlink(source_path, &link.target_path)?;
+            for ancestor in link.target.ancestors().skip(1) {
+                if ancestor == record.target || !ancestor.starts_with(&record.target) {
+                    break;
+                }
+
+                target_dirs.insert(ancestor.to_owned());
+            }
         }
 
-        if self.program_config.verbose {
-            println!(
-                "{}{} {}",
-                INDENT,
-                "Created".cyan(),
-                self.path_str(&link.target_path)
-            );
+        Ok(target_dirs)
+    }
+
+    fn plan_remove_links(&self, plan: &mut Plan) -> Result<BTreeSet<PathBuf>> {
+        let mut removed_dir: Option<PathBuf> = None;
+        let mut target_dirs = BTreeSet::new();
+
+        for link_result in self.links()? {
+            let link = link_result?;
+            if is_within(link.entry.path(), &removed_dir) {
+                continue;
+            }
+
+            if link.is_dir() && is_real_dir(&link.target_path) {
+                target_dirs.insert(link.target_path.clone());
+                continue;
+            }
+
+            if link.is_template() {
+                let checksum = template::checksum(self.render(&link)?.as_bytes());
+                self.plan_remove_file(&link.target_path, link.entry.path(), &[checksum], plan)?;
+                continue;
+            }
+
+            if !link.is_dir() && !self.mode(&link).is_symlink() {
+                let checksum = template::checksum(&fs::read(link.entry.path())?);
+                self.plan_remove_file(&link.target_path, link.entry.path(), &[checksum], plan)?;
+                continue;
+            }
+
+            self.plan_remove_link(&link.target_path, link.entry.path(), plan)?;
+            if link.is_dir() {
+                removed_dir = Some(link.entry.path().to_owned());
+            }
+        }
+
+        Ok(target_dirs)
+    }
+
+    fn plan_restore(&self, plan: &mut Plan, state: &State) -> Result<()> {
+        let record = match state.record(&self.path) {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+
+        for backup in record.backups.iter().rev() {
+            if fs::symlink_metadata(&backup.backup).is_err() {
+                plan.conflicts.push(Conflict::FileDoesNotExist { path: backup.backup.clone() });
+                continue;
+            }
+
+            if fs::symlink_metadata(&backup.original).is_ok() && !plan.is_removed(&backup.original) {
+                plan.conflicts.push(Conflict::FileExists { path: backup.original.clone() });
+                continue;
+            }
+
+            plan.operations.push(Operation::Restore {
+                backup: backup.backup.clone(),
+                original: backup.original.clone(),
+            });
         }
 
         Ok(())
     }
 
-    fn remove_link(&self, link: &Link) -> Result<()> {
-        if !self.program_config.dry_run {
-            if !self.program_config.force && !is_symlink(&link.target_path)? {
-                return Err(Error::NotSymlinkError(link.target_path.clone()));
+    /// Plans removing a dangling symlink at `target_path` if it points into
+    /// this package or replacing it is allowed, and otherwise reports it as a
+    /// conflict, returning whether it did. Links belonging to other packages
+    /// are left to `plan_link`.
+    fn plan_dangling_link(
+        &self,
+        target_path: &Path,
+        plan: &mut Plan,
+        state: &State,
+    ) -> Result<bool> {
+        if plan.is_removed(target_path) || fs::metadata(target_path).is_ok() {
+            return Ok(false);
+        }
+
+        let destination = match link_destination(target_path)? {
+            Some(destination) => destination,
+            None => return Ok(false),
+        };
+
+        if state.owner(target_path).is_some_and(|record| record.path != self.path) {
+            return Ok(false);
+        }
+
+        let is_replaceable = destination.starts_with(&self.path) || self.options.force ||
+            self.options.replace_dangling;
+
+        if is_replaceable {
+            plan.operations.push(Operation::RemovePath { path: target_path.to_owned() });
+            Ok(false)
+        } else {
+            plan.conflicts.push(Conflict::DanglingLink {
+                path: target_path.to_owned(),
+                destination,
+            });
+            Ok(true)
+        }
+    }
+
+    /// Decides whether a package directory should be merged into an existing
+    /// directory at its target path rather than linked as a whole, unfolding a
+    /// symlink to another package's directory or creating a directory for
+    /// conditional entries if necessary.
+    ///
+    /// What's planned takes precedence over what's on disk, so that a
+    /// directory inside one that is being unfolded is unfolded in turn rather
+    /// than merged into the other package's own directory.
+    fn plan_target_dir(&self, link: &Link, plan: &mut Plan, state: &State) -> Result<bool> {
+        let target_path = &link.target_path;
+        if plan.exists(target_path) {
+            let destination = match plan.planned_link(target_path) {
+                Some((source, relative)) => Some((source.to_owned(), relative)),
+                None if is_real_dir(target_path) => return Ok(true),
+                None => link_destination(target_path)?.map(|destination| {
+                    (destination, is_relative_link(target_path))
+                }),
+            };
+
+            if let Some((destination, relative)) = destination {
+                let operation = self.unfold_operation(target_path, &destination, relative, state)?;
+                if let Some(operation) = operation {
+                    plan.operations.push(operation);
+                    return Ok(true);
+                }
+            }
+        }
+
+        if !plan.exists(target_path) && self.needs_dir(link.entry.path()) {
+            plan.operations.push(Operation::CreateDir { path: target_path.clone() });
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Whether the package directory `dir` can't be linked as a whole, since
+    /// that would install every variant of its conditional entries under
+    /// their conditional names and its templates unrendered, and it can't be
+    /// copied or hard-linked as a whole.
+    fn needs_dir(&self, dir: &Path) -> bool {
+        contains_entry(dir, &|path: &Path| {
+            conditions::is_conditional(path) ||
+                (path.is_file() &&
+                    (template::is_template(path) || !self.path_mode(path).is_symlink()))
+        })
+    }
+
+    /// The links to the entries of `source_dir` that this package would
+    /// install, or `None` if the directory can't be linked as a whole.
+    fn dir_links(&self, source_dir: &Path) -> Result<Option<Vec<Link>>> {
+        if self.needs_dir(source_dir) {
+            return Ok(None);
+        }
+
+        let mut links = Vec::new();
+        for link_result in self.links()? {
+            let link = link_result?;
+            if link.entry.path().parent() == Some(source_dir) {
+                links.push(link);
             }
+        }
+
+        Ok(Some(links))
+    }
 
-            remove_path(&link.target_path)?;
+    /// Plans replacing a symlink at `target_path` to another package's
+    /// directory `destination` with a real directory containing links to that
+    /// package's entries, if it can be.
+    fn unfold_operation(
+        &self,
+        target_path: &Path,
+        destination: &Path,
+        relative: bool,
+        state: &State,
+    ) -> Result<Option<Operation>> {
+        if !destination.is_dir() {
+            return Ok(None);
         }
 
-        if self.program_config.verbose {
-            println!(
-                "{}{} {}",
-                INDENT,
-                "Removed".red(),
-                self.path_str(&link.target_path)
-            );
+        let other_package = match self.foreign_package(destination, state) {
+            Some(other_package) => other_package,
+            None => return Ok(None),
+        };
+
+        let links = match other_package.dir_links(destination)? {
+            Some(links) => links,
+            None => return Ok(None),
+        };
+
+        let links = links
+            .iter()
+            .filter_map(|link| {
+                link.target_path.file_name().map(|file_name| {
+                    LinkRecord {
+                        source: link.entry.path().to_owned(),
+                        target: target_path.join(file_name),
+                        mode: Mode::Symlink,
+                        checksum: None,
+                    }
+                })
+            })
+            .collect();
+
+        Ok(Some(Operation::Unfold {
+            path: target_path.to_owned(),
+            source: destination.to_owned(),
+            relative,
+            links,
+        }))
+    }
+
+    /// Plans replacing a directory with a symlink to another package's
+    /// directory if everything left in it links into that directory.
+    fn plan_fold(&self, target_path: &Path, plan: &mut Plan, state: &State) -> Result<()> {
+        if !is_real_dir(target_path) || plan.is_removed(target_path) {
+            return Ok(());
+        }
+
+        if let Some(source_dir) = self.foldable_source_dir(target_path, plan, state)? {
+            // Keeps the style of the links it replaces
+            let mut relative = false;
+            for entry_result in fs::read_dir(target_path)? {
+                let entry_path = entry_result?.path();
+                if !plan.is_removed(&entry_path) {
+                    relative |= match plan.planned_link(&entry_path) {
+                        Some((_, is_relative)) => is_relative,
+                        None => is_relative_link(&entry_path),
+                    };
+                }
+            }
+
+            plan.operations.push(Operation::Fold {
+                path: target_path.to_owned(),
+                source: source_dir,
+                relative,
+            });
         }
 
         Ok(())
     }
 
-    fn run_hook(&self, hook: &Hook) -> Result<()> {
-        if let Some(ref command_str) = hook.command {
-            self.run_command_str(command_str)
-        } else if let Some(ref script_name) = hook.script {
-            self.run_script_name(script_name)
+    /// The directory of another package that the remaining entries of
+    /// `target_path` all link into, including any folded already, if they're
+    /// exactly the links that package would install there and linking the
+    /// directory as a whole would install nothing else.
+    fn foldable_source_dir(
+        &self,
+        target_path: &Path,
+        plan: &Plan,
+        state: &State,
+    ) -> Result<Option<PathBuf>> {
+        let mut source_dir: Option<PathBuf> = None;
+        let mut entries = BTreeSet::new();
+
+        for entry_result in fs::read_dir(target_path)? {
+            let entry = entry_result?;
+            let entry_path = entry.path();
+            if plan.is_removed(&entry_path) {
+                continue;
+            }
+
+            let destination = match plan.planned_link(&entry_path) {
+                Some((source, _)) => source.to_owned(),
+                None => match link_destination(&entry_path)? {
+                    Some(destination) => destination,
+                    None => return Ok(None),
+                },
+            };
+
+            let parent = match destination.parent() {
+                Some(parent) => parent.to_owned(),
+                None => return Ok(None),
+            };
+
+            if *source_dir.get_or_insert_with(|| parent.clone()) != parent {
+                return Ok(None);
+            }
+
+            entries.insert((entry.file_name(), destination));
+        }
+
+        let source_dir = match source_dir {
+            Some(ref source_dir) if source_dir.is_dir() => source_dir.clone(),
+            _ => return Ok(None),
+        };
+
+        let other_package = match self.foreign_package(&source_dir, state) {
+            Some(other_package) => other_package,
+            None => return Ok(None),
+        };
+
+        let links = match other_package.dir_links(&source_dir)? {
+            Some(links) => links,
+            None => return Ok(None),
+        };
+
+        let other_entries: BTreeSet<_> = links
+            .iter()
+            .filter_map(|link| {
+                link.target_path
+                    .file_name()
+                    .map(|file_name| (file_name.to_owned(), link.entry.path().to_owned()))
+            })
+            .collect();
+
+        if entries == other_entries {
+            Ok(Some(source_dir))
         } else {
-            Ok(())
+            Ok(None)
         }
     }
 
-    fn run_command_str(&self, command_str: &str) -> Result<()> {
-        if self.program_config.verbose {
-            println!(
-                "{}{} `{}`{}",
-                INDENT,
-                "Running command".magenta(),
-                command_str,
-                "...".magenta()
-            );
+    /// The installed package other than this one that `path` belongs to.
+    fn foreign_package(&self, path: &Path, state: &State) -> Option<Package<'a>> {
+        let record = state.other_owner(path, &self.path)?;
+        Package::new(&record.path, self.options).ok()
+    }
+
+    fn plan_link(&self, link: &Link, plan: &mut Plan, state: &State) -> Result<()> {
+        // Links that are already installed are only recorded again, so that
+        // installing is idempotent
+        let is_unchanged = !plan.is_removed(&link.target_path) &&
+            self.link_status(link)? == self.installed_status(link);
+
+        if is_unchanged {
+            plan.operations.push(Operation::Unchanged {
+                source: link.entry.path().to_owned(),
+                target: link.target_path.clone(),
+                mode: if link.is_template() { Mode::Copy } else { self.mode(link) },
+            });
+            return Ok(());
         }
 
-        if !self.program_config.dry_run {
-            self.run_command(
-                command_str,
-                Command::new("sh")
-                    .arg("-c")
-                    .arg(command_str)
-                    .current_dir(&self.path),
-            )?;
+        if plan.exists(&link.target_path) {
+            let owner = state.owner(&link.target_path).filter(|record| record.path != self.path);
+            if let Some(record) = owner {
+                plan.conflicts.push(Conflict::OwnedByPackage {
+                    path: link.target_path.clone(),
+                    package: record.path.clone(),
+                });
+                return Ok(());
+            }
+
+            if self.options.force && self.options.backup {
+                self.plan_back_up(&link.target_path, plan)?;
+            } else if self.options.force {
+                plan.operations.push(Operation::RemovePath { path: link.target_path.clone() });
+            } else {
+                plan.conflicts.push(Conflict::FileExists { path: link.target_path.clone() });
+                return Ok(());
+            }
         }
 
+        let source = link.entry.path().to_owned();
+        let target = link.target_path.clone();
+
+        let operation = if link.is_template() {
+            Operation::RenderTemplate {
+                source,
+                target,
+                contents: self.render(link)?,
+            }
+        } else {
+            match self.mode(link) {
+                Mode::Symlink => {
+                    Operation::CreateSymlink {
+                        source,
+                        target,
+                        relative: self.config.relative.unwrap_or(false),
+                    }
+                }
+                Mode::Copy => Operation::CopyFile { source, target },
+                Mode::Hardlink => Operation::HardLink { source, target },
+            }
+        };
+
+        plan.operations.push(operation);
+
         Ok(())
     }
 
-    fn run_command(&self, command_str: &str, command: &mut Command) -> Result<()> {
-        let output = command.output()?;
-        if output.status.success() {
-            Ok(())
-        } else {
-            let message = String::from_utf8_lossy(&output.stderr).into_owned();
-            Err(Error::CommandError(command_str.to_owned(), message))
+    fn render(&self, link: &Link) -> Result<String> {
+        let template_str = fs::read_to_string(link.entry.path())?;
+        template::render(&template_str, &self.facts)
+            .map_err(|message| Error::TemplateError(link.entry.path().to_owned(), message))
+    }
+
+    /// Plans replacing a package file with the file at its target path, or
+    /// reports a conflict if one of them is a directory and the other isn't,
+    /// returning whether it did.
+    ///
+    /// Templates are never adopted, since their targets are rendered from
+    /// them, and neither are copies that are already installed or have been
+    /// changed since.
+    fn plan_adopt(&self, link: &Link, plan: &mut Plan) -> Result<bool> {
+        if link.is_template() {
+            return Ok(false);
         }
+
+        let status = self.link_status(link)?;
+        if status == self.installed_status(link) || status == LinkStatus::Modified {
+            return Ok(false);
+        }
+
+        let is_target_dir = match status {
+            LinkStatus::File => false,
+            LinkStatus::Directory => true,
+            _ => return Ok(false),
+        };
+
+        if is_target_dir != link.is_dir() {
+            plan.conflicts.push(Conflict::TypeMismatch {
+                path: link.target_path.clone(),
+                source: link.entry.path().to_owned(),
+            });
+            return Ok(true);
+        }
+
+        plan.operations.push(Operation::Adopt {
+            target: link.target_path.clone(),
+            source: link.entry.path().to_owned(),
+        });
+        Ok(false)
+    }
+
+    /// Plans moving a file out of the way into this package's backup
+    /// directory, where `restore-backup` can find it later.
+    fn plan_back_up(&self, target_path: &Path, plan: &mut Plan) -> Result<()> {
+        let relative_path = target_path.strip_prefix(&plan.target)?;
+        let backup_path = self.backup_root()?
+            .join(self.name())
+            .join(&plan.timestamp)
+            .join(relative_path);
+
+        plan.operations.push(Operation::Backup {
+            path: target_path.to_owned(),
+            backup: backup_path,
+        });
+        Ok(())
     }
 
-    fn run_script_name(&self, script_name: &str) -> Result<()> {
-        let script_path = self.path.join(script_name);
+    /// Plans removing the symlink at `target_path` if it still points to
+    /// `source_path`. Anything else there is left alone, even with `--force`,
+    /// and nothing there at all is fine.
+    fn plan_remove_link(
+        &self,
+        target_path: &Path,
+        source_path: &Path,
+        plan: &mut Plan,
+    ) -> Result<()> {
+        let reason = match link_destination(target_path) {
+            Ok(Some(ref destination)) if destination == source_path => {
+                plan.operations.push(Operation::RemovePath { path: target_path.to_owned() });
+                return Ok(());
+            }
+            Ok(Some(destination)) => format!("links to `{}` instead", destination.display()),
+            Ok(None) if fs::symlink_metadata(target_path).is_ok() => "not a symlink".to_owned(),
+            Ok(None) => "already removed".to_owned(),
+            Err(error) => return Err(error),
+        };
+
+        plan.operations.push(Operation::Forget {
+            path: target_path.to_owned(),
+            reason,
+        });
+        Ok(())
+    }
 
-        if self.program_config.verbose {
-            println!(
-                "{}{} {}{}",
-                INDENT,
-                "Running script".magenta(),
-                self.path_str(&script_path),
-                "...".magenta()
-            );
+    /// Plans removing a file that was copied, hard-linked, or rendered rather
+    /// than symlinked, unless it has been changed since, in which case it's
+    /// left in place.
+    fn plan_remove_file(
+        &self,
+        target_path: &Path,
+        source_path: &Path,
+        checksums: &[String],
+        plan: &mut Plan,
+    ) -> Result<()> {
+        let is_file = fs::symlink_metadata(target_path).is_ok_and(|metadata| metadata.is_file());
+        if !is_file {
+            return self.plan_remove_link(target_path, source_path, plan);
         }
 
-        if !self.program_config.dry_run {
-            self.run_command(
-                script_name,
-                Command::new(script_path).current_dir(&self.path),
-            )?;
+        let is_modified = !is_same_file(target_path, source_path) &&
+            fs::read(target_path).map_or(true, |contents| {
+                !checksums.contains(&template::checksum(&contents))
+            });
+
+        if is_modified && !self.options.force {
+            plan.operations.push(Operation::Forget {
+                path: target_path.to_owned(),
+                reason: "modified since it was installed".to_owned(),
+            });
+        } else {
+            plan.operations.push(Operation::RemovePath { path: target_path.to_owned() });
         }
 
         Ok(())
     }
 
+    /// How the file at `link` is installed, if it isn't a directory.
+    fn mode(&self, link: &Link) -> Mode {
+        if link.is_dir() {
+            Mode::Symlink
+        } else {
+            self.path_mode(link.entry.path())
+        }
+    }
+
+    /// How the package file at `source_path` is installed, from the longest
+    /// pattern in `modes` that matches it, or else `mode`.
+    fn path_mode(&self, source_path: &Path) -> Mode {
+        let relative_path = match source_path.strip_prefix(&self.path) {
+            Ok(relative_path) => conditions::strip_conditions(relative_path),
+            Err(_) => return Mode::Symlink,
+        };
+
+        self.mode_overrides
+            .iter()
+            .find(|(overrides, _)| overrides.matched(&relative_path, false).is_whitelist())
+            .map(|&(_, mode)| mode)
+            .or(self.config.mode)
+            .unwrap_or_default()
+    }
+
     pub fn links(&'a self) -> Result<Links<'a>> {
-        Links::new(&self)
+        Links::new(self)
+    }
+
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or_else(
+            || self.path.to_string_lossy().into_owned(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+    }
+
+    fn backup_root(&self) -> Result<PathBuf> {
+        let path = match self.options.backup_dir {
+            Some(ref path) => path.clone(),
+            None => match self.config.backup_dir {
+                Some(ref path_str) => expand_path(path_str)?,
+                None => State::backups_dir()?,
+            },
+        };
+
+        // Backups are recorded by path, so they have to be found again from
+        // wherever `restore-backup` is run
+        if path.is_relative() {
+            Ok(env::current_dir()?.join(path))
+        } else {
+            Ok(path)
+        }
     }
 
     fn target_root(&self) -> Result<PathBuf> {
-        let path_str = self.config.target.as_ref().map_or(
-            DEFAULT_TARGET,
-            String::as_str,
-        );
-        let full_path_str = shellexpand::full(path_str)?.into_owned();
-        Ok(PathBuf::from(full_path_str))
+        let path = match self.options.target {
+            Some(ref path) => path.clone(),
+            None => expand_path(self.config.target.as_deref().unwrap_or(DEFAULT_TARGET))?,
+        };
+
+        if path.is_relative() {
+            Ok(env::current_dir()?.join(path))
+        } else {
+            Ok(path)
+        }
     }
 
     fn target_path(&self, source_path: &Path) -> Result<PathBuf> {
-        let relative_path = source_path.strip_prefix(&self.path)?;
+        let mut relative_path = conditions::strip_conditions(source_path.strip_prefix(&self.path)?);
+        if template::is_template(source_path) && source_path.is_file() {
+            relative_path = template::strip_suffix(&relative_path);
+        }
+
         let target_root = self.target_root()?;
         Ok(target_root.join(relative_path))
     }
@@ -314,10 +1014,10 @@ impl<'a> Package<'a> {
         let overrides = self.build_overrides()?;
         Ok(
             WalkBuilder::new(&self.path)
-                .max_depth(Some(1))
                 .hidden(false)
                 .git_global(true)
                 .overrides(overrides)
+                .sort_by(|a, b| a.cmp(b))
                 .build(),
         )
     }
@@ -325,33 +1025,56 @@ impl<'a> Package<'a> {
     fn build_overrides(&self) -> Result<Override> {
         let mut builder = OverrideBuilder::new(&self.path);
         add_ignore_glob(&mut builder, IGNORE_FILENAME)?;
-        add_ignore_glob(&mut builder, config::DEFAULT_FILENAME)?;
+        add_ignore_glob(&mut builder, &root_glob(config::DEFAULT_FILENAME))?;
 
         for script_name in self.config.script_names() {
-            add_ignore_glob(&mut builder, script_name)?;
+            add_ignore_glob(&mut builder, &root_glob(script_name))?;
+        }
+
+        for pattern in &self.config.ignore {
+            add_ignore_glob(&mut builder, pattern)?;
         }
 
         Ok(builder.build()?)
     }
+}
 
-    fn path_str(&'a self, absolute_path: &'a Path) -> ColoredString {
-        let path = if absolute_path == self.path {
-            absolute_path
-        } else if let Ok(relative_path) = absolute_path.strip_prefix(&self.path) {
-            relative_path
-        } else {
-            absolute_path
-        };
+/// Plans removing each of `dirs` that will be empty, innermost first.
+fn plan_remove_empty_dirs(dirs: &[PathBuf], plan: &mut Plan) -> Result<()> {
+    let mut dirs = dirs.to_vec();
+    dirs.sort_by_key(|dir| Reverse(dir.components().count()));
 
-        let raw_str = path.to_string_lossy();
-        let safe_str = if let Some(_) = raw_str.find(char::is_whitespace) {
-            format!("`{}`", raw_str)
-        } else {
-            raw_str.to_string()
-        };
+    for dir in dirs {
+        if !is_real_dir(&dir) || plan.is_removed(&dir) {
+            continue;
+        }
+
+        let mut is_empty = true;
+        for entry_result in fs::read_dir(&dir)? {
+            is_empty &= plan.is_removed(&entry_result?.path());
+        }
 
-        safe_str.bold()
+        if is_empty {
+            plan.operations.push(Operation::RemoveDir { path: dir });
+        }
     }
+
+    Ok(())
+}
+
+fn build_mode_overrides(package_path: &Path, config: &Config) -> Result<Vec<(Override, Mode)>> {
+    let mut patterns: Vec<(&String, Mode)> =
+        config.modes.iter().map(|(pattern, &mode)| (pattern, mode)).collect();
+    patterns.sort_by_key(|(pattern, _)| Reverse(pattern.len()));
+
+    let mut mode_overrides = Vec::new();
+    for (pattern, mode) in patterns {
+        let mut builder = OverrideBuilder::new(package_path);
+        builder.add(pattern)?;
+        mode_overrides.push((builder.build()?, mode));
+    }
+
+    Ok(mode_overrides)
 }
 
 fn add_ignore_glob(builder: &mut OverrideBuilder, glob: &str) -> Result<()> {
@@ -360,15 +1083,390 @@ fn add_ignore_glob(builder: &mut OverrideBuilder, glob: &str) -> Result<()> {
     Ok(())
 }
 
-fn is_symlink(path: &Path) -> Result<bool> {
-    let metadata = fs::symlink_metadata(path)?;
-    Ok(metadata.file_type().is_symlink())
+fn root_glob(path: &str) -> String {
+    format!("/{}", path.trim_start_matches("./"))
+}
+
+/// Finds the packages in `root`, i.e. the directories in it that aren't
+/// hidden, optionally only those with a `.scrubjay.toml` file.
+pub fn find_packages(root: &Path, configured_only: bool) -> Result<Vec<PathBuf>> {
+    let mut package_paths = Vec::new();
+
+    for entry_result in fs::read_dir(root)? {
+        let path = entry_result?.path();
+        let is_hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if is_hidden || !path.is_dir() {
+            continue;
+        }
+
+        if configured_only && !path.join(config::DEFAULT_FILENAME).is_file() {
+            continue;
+        }
+
+        package_paths.push(path);
+    }
+
+    package_paths.sort();
+    Ok(package_paths)
+}
+
+fn expand_path(path_str: &str) -> Result<PathBuf> {
+    let full_path_str = shellexpand::full(path_str)?.into_owned();
+    Ok(PathBuf::from(full_path_str))
+}
+
+fn is_within(path: &Path, dir: &Option<PathBuf>) -> bool {
+    dir.as_ref().is_some_and(|dir| path.starts_with(dir))
+}
+
+/// Whether `path` and `other_path` are hard links to the same file.
+fn is_same_file(path: &Path, other_path: &Path) -> bool {
+    match (fs::symlink_metadata(path), fs::metadata(other_path)) {
+        (Ok(metadata), Ok(other_metadata)) => {
+            metadata.dev() == other_metadata.dev() && metadata.ino() == other_metadata.ino()
+        }
+        _ => false,
+    }
+}
+
+fn is_real_dir(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir())
 }
 
-fn remove_path(path: &Path) -> Result<()> {
-    if path.is_dir() {
-        Ok(fs::remove_dir_all(path)?)
-    } else {
-        Ok(fs::remove_file(path)?)
+/// Whether `predicate` holds for any entry in `dir`, at any depth.
+fn contains_entry(dir: &Path, predicate: &dyn Fn(&Path) -> bool) -> bool {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+
+    entries.filter_map(|entry_result| entry_result.ok()).any(|entry| {
+        let path = entry.path();
+        predicate(&path) ||
+            (entry.file_type().is_ok_and(|file_type| file_type.is_dir()) &&
+                contains_entry(&path, predicate))
+    })
+}
+
+/// Returns the absolute path a symlink points to, whether it was linked with
+/// an absolute or relative path, or `None` if `path` is not a symlink.
+fn link_destination(path: &Path) -> Result<Option<PathBuf>> {
+    match fs::symlink_metadata(path) {
+        Ok(ref metadata) if metadata.file_type().is_symlink() => {
+            let destination = fs::read_link(path)?;
+            if destination.is_absolute() {
+                return Ok(Some(destination));
+            }
+
+            // Relative links are resolved from where their parent really is
+            let parent = match path.parent() {
+                Some(parent) => fs::canonicalize(parent)?,
+                None => PathBuf::new(),
+            };
+
+            Ok(Some(normalize_path(&parent.join(destination))))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn is_relative_link(path: &Path) -> bool {
+    fs::read_link(path).is_ok_and(|destination| destination.is_relative())
+}
+
+/// Removes `.` and `..` components from `path` without touching the
+/// filesystem.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            // `..` at the root is the root, and leading `..`s of relative
+            // paths have nothing to cancel out
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            _ => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// The path to `path` from the directory `base_dir`, both of which are
+/// absolute.
+fn relative_path(base_dir: &Path, path: &Path) -> PathBuf {
+    let base_components: Vec<_> = base_dir.components().collect();
+    let components: Vec<_> = path.components().collect();
+    let common_count = base_components
+        .iter()
+        .zip(&components)
+        .take_while(|(base_component, component)| base_component == component)
+        .count();
+
+    let mut relative_path = PathBuf::new();
+    for _ in common_count..base_components.len() {
+        relative_path.push(Component::ParentDir);
+    }
+
+    for component in &components[common_count..] {
+        relative_path.push(component);
+    }
+
+    relative_path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+    use super::testing::TempDir;
+
+    /// Performs `action` on the package `dots/<package_name>` with `home` as
+    /// its target.
+    fn run(dir: &TempDir, package_name: &str, action: Action) -> Result<()> {
+        let options = Options::new().target(dir.join("home"));
+        let package = Package::new(&dir.join("dots").join(package_name), &options)?;
+        package.plan(action)?.execute(&Executor::new(&options))
+    }
+
+    fn entry_names(path: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(path)
+            .unwrap()
+            .map(|entry_result| entry_result.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn read_link(path: &Path) -> PathBuf {
+        fs::read_link(path).unwrap()
+    }
+
+    #[test]
+    fn unfolds_and_folds_shared_directories() {
+        let dir = TempDir::new("fold");
+        let _state = dir.lock_state();
+        let home = dir.dir("home");
+        dir.file("dots/a/.config/fish/config.fish", "");
+        dir.file("dots/b/.config/nvim/init.lua", "");
+
+        run(&dir, "a", Action::Install).unwrap();
+        assert_eq!(read_link(&home.join(".config")), dir.join("dots/a/.config"));
+
+        run(&dir, "b", Action::Install).unwrap();
+        assert!(is_real_dir(&home.join(".config")));
+        assert_eq!(entry_names(&home.join(".config")), ["fish", "nvim"]);
+        assert_eq!(read_link(&home.join(".config/fish")), dir.join("dots/a/.config/fish"));
+        assert_eq!(entry_names(&dir.join("dots/a/.config")), ["fish"]);
+
+        run(&dir, "b", Action::Uninstall).unwrap();
+        assert_eq!(read_link(&home.join(".config")), dir.join("dots/a/.config"));
+        assert_eq!(entry_names(&dir.join("dots/a/.config")), ["fish"]);
+    }
+
+    #[test]
+    fn unfolds_and_folds_nested_shared_directories() {
+        let dir = TempDir::new("fold-nested");
+        let _state = dir.lock_state();
+        let home = dir.dir("home");
+        dir.file("dots/a/.config/nvim/init.lua", "");
+        dir.file("dots/b/.config/nvim/plug.lua", "");
+
+        run(&dir, "a", Action::Install).unwrap();
+        run(&dir, "b", Action::Install).unwrap();
+        assert!(is_real_dir(&home.join(".config")));
+        assert!(is_real_dir(&home.join(".config/nvim")));
+        assert_eq!(entry_names(&home.join(".config")), ["nvim"]);
+        assert_eq!(entry_names(&home.join(".config/nvim")), ["init.lua", "plug.lua"]);
+        assert_eq!(
+            read_link(&home.join(".config/nvim/init.lua")),
+            dir.join("dots/a/.config/nvim/init.lua")
+        );
+        assert_eq!(
+            read_link(&home.join(".config/nvim/plug.lua")),
+            dir.join("dots/b/.config/nvim/plug.lua")
+        );
+        assert_eq!(entry_names(&dir.join("dots/a/.config/nvim")), ["init.lua"]);
+
+        run(&dir, "b", Action::Uninstall).unwrap();
+        assert_eq!(entry_names(&home), [".config"]);
+        assert_eq!(read_link(&home.join(".config")), dir.join("dots/a/.config"));
+        assert_eq!(entry_names(&dir.join("dots/a/.config/nvim")), ["init.lua"]);
+    }
+
+    #[test]
+    fn never_folds_existing_directories() {
+        let dir = TempDir::new("fold-existing");
+        let _state = dir.lock_state();
+        let home = dir.dir("home");
+        dir.dir("home/.config");
+        dir.file("dots/a/.config/fish/config.fish", "");
+        dir.file("dots/b/.config/nvim/init.lua", "");
+
+        run(&dir, "a", Action::Install).unwrap();
+        run(&dir, "b", Action::Install).unwrap();
+        run(&dir, "b", Action::Uninstall).unwrap();
+        assert!(is_real_dir(&home.join(".config")));
+        assert_eq!(entry_names(&home.join(".config")), ["fish"]);
+    }
+
+    #[test]
+    fn adopts_existing_files() {
+        let dir = TempDir::new("adopt");
+        let _state = dir.lock_state();
+        let home = dir.dir("home");
+        let source = dir.file("dots/a/.vimrc", "package");
+        dir.file("home/.vimrc", "mine");
+
+        run(&dir, "a", Action::Adopt).unwrap();
+        assert_eq!(fs::read_to_string(&source).unwrap(), "mine");
+        assert_eq!(read_link(&home.join(".vimrc")), source);
+    }
+
+    #[test]
+    fn never_adopts_files_over_directories() {
+        let dir = TempDir::new("adopt-mismatch");
+        let _state = dir.lock_state();
+        dir.file("dots/a/.emacs.d/init.el", "");
+        dir.file("dots/a/.emacs.d/local.el", "");
+        dir.file("home/.emacs.d", "mine");
+
+        assert!(run(&dir, "a", Action::Adopt).is_err());
+        assert_eq!(entry_names(&dir.join("dots/a/.emacs.d")), ["init.el", "local.el"]);
+        assert_eq!(fs::read_to_string(dir.join("home/.emacs.d")).unwrap(), "mine");
+    }
+
+    #[test]
+    fn never_adopts_rendered_templates() {
+        let dir = TempDir::new("adopt-template");
+        let _state = dir.lock_state();
+        let home = dir.dir("home");
+        let source = dir.file("dots/a/.gitconfig.tmpl", "os = {{ os }}");
+
+        run(&dir, "a", Action::Install).unwrap();
+        run(&dir, "a", Action::Adopt).unwrap();
+        assert_eq!(fs::read_to_string(&source).unwrap(), "os = {{ os }}");
+        assert_eq!(
+            fs::read_to_string(home.join(".gitconfig")).unwrap(),
+            format!("os = {}", env::consts::OS)
+        );
+    }
+
+    #[test]
+    fn never_adopts_installed_copies() {
+        let dir = TempDir::new("adopt-copy");
+        let _state = dir.lock_state();
+        let source = dir.file("dots/a/.vimrc", "package");
+        dir.file("dots/a/.scrubjay.toml", "mode = \"copy\"");
+
+        run(&dir, "a", Action::Install).unwrap();
+
+        let options = Options::new().target(dir.join("home"));
+        let package = Package::new(&dir.join("dots/a"), &options).unwrap();
+        let plan = package.plan(Action::Adopt).unwrap();
+        assert!(plan.is_ok());
+        assert!(!plan.operations.iter().any(|operation| {
+            matches!(*operation, Operation::Adopt { .. })
+        }));
+        assert_eq!(fs::read_to_string(&source).unwrap(), "package");
+    }
+
+    #[test]
+    fn reports_which_package_declares_a_conflict() {
+        let dir = TempDir::new("declared-conflict");
+        let _state = dir.lock_state();
+        dir.file("dots/a/.scrubjay.toml", "conflicts = [\"bash\"]");
+        dir.file("dots/b/.scrubjay.toml", "provides = [\"bash\"]");
+
+        run(&dir, "a", Action::Install).unwrap();
+
+        let options = Options::new().target(dir.join("home"));
+        let package = Package::new(&dir.join("dots/b"), &options).unwrap();
+        let plan = package.plan(Action::Install).unwrap();
+        match plan.conflicts.as_slice() {
+            [Conflict::DeclaredConflict { ref package, ref name }] => {
+                assert_eq!(*package, dir.join("dots/a"));
+                assert_eq!(name, "bash");
+            }
+            conflicts => panic!("expected a declared conflict, got {:?}", conflicts),
+        }
+    }
+
+    #[test]
+    fn never_unfolds_links_outside_installed_packages() {
+        let dir = TempDir::new("unfold-foreign");
+        let _state = dir.lock_state();
+        let home = dir.dir("home");
+        dir.file("dots/other/.config/fish/config.fish", "");
+        dir.file("dots/b/.config/nvim/init.lua", "");
+        symlink(dir.join("dots/other/.config"), home.join(".config")).unwrap();
+
+        assert!(run(&dir, "b", Action::Install).is_err());
+        assert_eq!(read_link(&home.join(".config")), dir.join("dots/other/.config"));
+        assert_eq!(entry_names(&dir.join("dots/other/.config")), ["fish"]);
+    }
+
+    fn relative(base_dir: &str, path: &str) -> PathBuf {
+        relative_path(Path::new(base_dir), Path::new(path))
+    }
+
+    fn normalize(path: &str) -> PathBuf {
+        normalize_path(Path::new(path))
+    }
+
+    #[test]
+    fn relative_path_descends_into_subdirectories() {
+        assert_eq!(
+            relative("/home/jo", "/home/jo/dots/vim/.vimrc"),
+            PathBuf::from("dots/vim/.vimrc")
+        );
+    }
+
+    #[test]
+    fn relative_path_climbs_out_of_directories() {
+        assert_eq!(
+            relative("/home/jo/.config/fish", "/home/jo/dots/fish/.config/fish/config.fish"),
+            PathBuf::from("../../dots/fish/.config/fish/config.fish")
+        );
+        assert_eq!(relative("/home/jo/.config", "/etc/hosts"), PathBuf::from("../../../etc/hosts"));
+    }
+
+    #[test]
+    fn relative_path_compares_whole_components() {
+        assert_eq!(relative("/home/jo", "/home/jordan/.vimrc"), PathBuf::from("../jordan/.vimrc"));
+    }
+
+    #[test]
+    fn relative_path_to_the_base_dir_is_empty() {
+        assert_eq!(relative("/home/jo", "/home/jo"), PathBuf::new());
+    }
+
+    #[test]
+    fn normalize_path_resolves_parent_dirs() {
+        assert_eq!(normalize("/home/jo/.config/../dots/./vim"), PathBuf::from("/home/jo/dots/vim"));
+        assert_eq!(normalize("/home/jo/a/b/../../c"), PathBuf::from("/home/jo/c"));
+    }
+
+    #[test]
+    fn normalize_path_stops_at_the_root() {
+        assert_eq!(normalize("/home/../../etc"), PathBuf::from("/etc"));
+    }
+
+    #[test]
+    fn normalize_path_keeps_leading_parent_dirs_of_relative_paths() {
+        assert_eq!(normalize("../a/../../b"), PathBuf::from("../../b"));
+    }
+
+    #[test]
+    fn normalize_path_inverts_relative_path() {
+        let base_dir = Path::new("/home/jo/.config/fish");
+        let path = Path::new("/home/jo/dots/fish/.config/fish/config.fish");
+        assert_eq!(normalize_path(&base_dir.join(relative_path(base_dir, path))), path);
     }
 }
@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use super::{Config, NullReporter, Reporter};
+
+/// Settings that apply to every package an action is performed on, built up
+/// with chained calls, e.g. `Options::new().dry_run(true).verbose(true)`.
+#[derive(Clone)]
+pub struct Options {
+    /// Reports what would happen without changing anything.
+    pub dry_run: bool,
+    /// Allows existing files to be replaced (after backing them up) or
+    /// removed.
+    pub force: bool,
+    /// Allows dangling symlinks to be replaced, which `force` also does.
+    pub replace_dangling: bool,
+    pub verbose: bool,
+    /// Overrides the target directory of every package.
+    pub target: Option<PathBuf>,
+    /// Overrides where replaced files are backed up.
+    pub backup_dir: Option<PathBuf>,
+    /// Whether files replaced with `force` are backed up first.
+    pub backup: bool,
+    /// Settings for any package whose own config leaves them unset.
+    pub defaults: Config,
+    /// Where events are sent; nothing is reported by default.
+    pub reporter: Rc<dyn Reporter>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            dry_run: false,
+            force: false,
+            replace_dangling: false,
+            verbose: false,
+            target: None,
+            backup_dir: None,
+            backup: true,
+            defaults: Config::default(),
+            reporter: Rc::new(NullReporter),
+        }
+    }
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options::default()
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Options {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn force(mut self, force: bool) -> Options {
+        self.force = force;
+        self
+    }
+
+    pub fn replace_dangling(mut self, replace_dangling: bool) -> Options {
+        self.replace_dangling = replace_dangling;
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Options {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn target<P: Into<PathBuf>>(mut self, target: P) -> Options {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn backup_dir<P: Into<PathBuf>>(mut self, backup_dir: P) -> Options {
+        self.backup_dir = Some(backup_dir.into());
+        self
+    }
+
+    pub fn backup(mut self, backup: bool) -> Options {
+        self.backup = backup;
+        self
+    }
+
+    pub fn defaults(mut self, defaults: Config) -> Options {
+        self.defaults = defaults;
+        self
+    }
+
+    pub fn reporter<R: Reporter + 'static>(mut self, reporter: R) -> Options {
+        self.reporter = Rc::new(reporter);
+        self
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Error, Executor, LinkRecord, Mode, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Install,
+    Uninstall,
+    Reinstall,
+    Adopt,
+    RestoreBackup,
+    Prune,
+}
+
+impl Action {
+    /// The name of the action, as used on the command line.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Action::Install => "install",
+            Action::Uninstall => "uninstall",
+            Action::Reinstall => "reinstall",
+            Action::Adopt => "adopt",
+            Action::RestoreBackup => "restore-backup",
+            Action::Prune => "prune",
+        }
+    }
+}
+
+/// A single change to the filesystem, or a hook to run, as part of a `Plan`.
+///
+/// Symlinks are created with paths relative to their parent directories if
+/// `relative` is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Operation {
+    CreateSymlink {
+        source: PathBuf,
+        target: PathBuf,
+        relative: bool,
+    },
+    CopyFile { source: PathBuf, target: PathBuf },
+    HardLink { source: PathBuf, target: PathBuf },
+    CreateDir { path: PathBuf },
+    RemoveDir { path: PathBuf },
+    RenderTemplate { source: PathBuf, target: PathBuf, contents: String },
+    RemovePath { path: PathBuf },
+    Backup { path: PathBuf, backup: PathBuf },
+    Adopt { target: PathBuf, source: PathBuf },
+    Restore { backup: PathBuf, original: PathBuf },
+    /// Replaces a symlink to another package's directory with a directory
+    /// containing `links`, that package's links to the entries in it.
+    Unfold {
+        path: PathBuf,
+        source: PathBuf,
+        relative: bool,
+        links: Vec<LinkRecord>,
+    },
+    Fold {
+        path: PathBuf,
+        source: PathBuf,
+        relative: bool,
+    },
+    /// Keeps a file that is already installed, recording it as installed in
+    /// `mode`.
+    Unchanged {
+        source: PathBuf,
+        target: PathBuf,
+        mode: Mode,
+    },
+    /// Stops tracking a path without touching it, e.g. because it has been
+    /// changed since it was installed.
+    Forget { path: PathBuf, reason: String },
+    /// Leaves a path alone, e.g. a directory that couldn't be read.
+    Skip { path: PathBuf, reason: String },
+    RunHook {
+        name: String,
+        command: Option<String>,
+        script: Option<PathBuf>,
+        dir: PathBuf,
+        env: BTreeMap<String, String>,
+    },
+}
+
+/// Something that would make a `Plan` fail if it were executed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Conflict {
+    FileExists { path: PathBuf },
+    FileDoesNotExist { path: PathBuf },
+    DanglingLink { path: PathBuf, destination: PathBuf },
+    OwnedByPackage { path: PathBuf, package: PathBuf },
+    ConflictingPackage { name: String, package: PathBuf },
+    /// An installed package whose own `conflicts` name this one.
+    DeclaredConflict { package: PathBuf, name: String },
+    /// A file at the target of a package directory, or vice versa, which
+    /// can't be adopted.
+    TypeMismatch { path: PathBuf, source: PathBuf },
+}
+
+impl Conflict {
+    pub fn to_error(&self) -> Error {
+        match *self {
+            Conflict::FileExists { ref path } => Error::FileExistsError(path.clone()),
+            Conflict::FileDoesNotExist { ref path } => Error::FileDoesNotExistError(path.clone()),
+            Conflict::DanglingLink { ref path, ref destination } => {
+                Error::DanglingLinkError(path.clone(), destination.clone())
+            }
+            Conflict::OwnedByPackage { ref path, ref package } => {
+                Error::OwnedFileError(path.clone(), package.clone())
+            }
+            Conflict::ConflictingPackage { ref name, ref package } => {
+                Error::ConflictingPackageError(name.clone(), package.clone())
+            }
+            Conflict::DeclaredConflict { ref package, ref name } => {
+                Error::DeclaredConflictError(package.clone(), name.clone())
+            }
+            Conflict::TypeMismatch { ref path, ref source } => {
+                Error::TypeMismatchError(path.clone(), source.clone())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.to_error(), fmt)
+    }
+}
+
+/// Everything that performing an action on a package would do, worked out
+/// against the current state of the filesystem without changing anything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub action: Action,
+    pub package: PathBuf,
+    pub target: PathBuf,
+    pub timestamp: String,
+    pub operations: Vec<Operation>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Plan {
+    pub fn new(action: Action, package: &Path, target: &Path) -> Plan {
+        Plan {
+            action,
+            package: package.to_owned(),
+            target: target.to_owned(),
+            timestamp: timestamp(SystemTime::now()),
+            operations: Vec::new(),
+            conflicts: Vec::new(),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    pub fn execute(&self, executor: &Executor) -> Result<()> {
+        executor.execute(self)
+    }
+
+    /// Whether anything, including a dangling symlink, will exist at `path`
+    /// once the operations planned so far have been executed.
+    pub fn exists(&self, path: &Path) -> bool {
+        self.planned_existence(path).unwrap_or_else(|| fs::symlink_metadata(path).is_ok())
+    }
+
+    /// Whether an operation planned so far removes whatever is at `path`.
+    pub fn is_removed(&self, path: &Path) -> bool {
+        self.planned_existence(path) == Some(false)
+    }
+
+    /// Where the symlink at `path` will point once the operations planned so
+    /// far have been executed, and whether it will be relative, if one is
+    /// planned there, including by unfolding a directory containing it.
+    pub fn planned_link(&self, path: &Path) -> Option<(&Path, bool)> {
+        for operation in self.operations.iter().rev() {
+            match *operation {
+                Operation::CreateSymlink {
+                    ref source,
+                    ref target,
+                    relative,
+                } if target == path => return Some((source, relative)),
+                Operation::Fold {
+                    path: ref folded_path,
+                    ref source,
+                    relative,
+                } if folded_path == path => return Some((source, relative)),
+                Operation::Unfold {
+                    ref links,
+                    relative,
+                    ..
+                } => {
+                    if let Some(link) = links.iter().find(|link| link.target == path) {
+                        return Some((&link.source, relative));
+                    }
+                }
+                _ => {}
+            }
+
+            if is_planned_at(operation, path) {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    fn planned_existence(&self, path: &Path) -> Option<bool> {
+        for operation in self.operations.iter().rev() {
+            // An unfolded directory only contains the links it's unfolded
+            // into, whatever its symlink currently points to
+            if let Operation::Unfold {
+                path: ref unfolded_path,
+                ref links,
+                ..
+            } = *operation
+            {
+                if let Some(child) = child_of(unfolded_path, path) {
+                    if !links.iter().any(|link| link.target == child) {
+                        return Some(false);
+                    }
+                }
+            }
+
+            match *operation {
+                Operation::CreateSymlink { target: ref created_path, .. } |
+                Operation::CopyFile { target: ref created_path, .. } |
+                Operation::HardLink { target: ref created_path, .. } |
+                Operation::CreateDir { path: ref created_path } |
+                Operation::RenderTemplate { target: ref created_path, .. } |
+                Operation::Restore { original: ref created_path, .. } |
+                Operation::Unfold { path: ref created_path, .. } |
+                Operation::Fold { path: ref created_path, .. } if created_path == path => {
+                    return Some(true)
+                }
+                Operation::RemovePath { path: ref removed_path } |
+                Operation::RemoveDir { path: ref removed_path } |
+                Operation::Backup { path: ref removed_path, .. } |
+                Operation::Adopt { target: ref removed_path, .. } if removed_path == path => {
+                    return Some(false)
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// Whether `operation` creates or removes something at `path`.
+fn is_planned_at(operation: &Operation, path: &Path) -> bool {
+    match *operation {
+        Operation::CreateSymlink { target: ref planned_path, .. } |
+        Operation::CopyFile { target: ref planned_path, .. } |
+        Operation::HardLink { target: ref planned_path, .. } |
+        Operation::CreateDir { path: ref planned_path } |
+        Operation::RenderTemplate { target: ref planned_path, .. } |
+        Operation::Restore { original: ref planned_path, .. } |
+        Operation::Unfold { path: ref planned_path, .. } |
+        Operation::Fold { path: ref planned_path, .. } |
+        Operation::RemovePath { path: ref planned_path } |
+        Operation::RemoveDir { path: ref planned_path } |
+        Operation::Backup { path: ref planned_path, .. } |
+        Operation::Adopt { target: ref planned_path, .. } => planned_path == path,
+        _ => false,
+    }
+}
+
+/// The entry of `dir` that `path` is or is inside of, if it's inside `dir`.
+fn child_of(dir: &Path, path: &Path) -> Option<PathBuf> {
+    let relative_path = path.strip_prefix(dir).ok()?;
+    relative_path.components().next().map(|component| dir.join(component))
+}
+
+/// Formats a time as a compact UTC timestamp, e.g. `20170704T120000Z`.
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let days = (seconds / 86_400) as i64;
+    let seconds_of_day = seconds % 86_400;
+
+    // Converts days since the epoch to a civil date, per Howard Hinnant's
+    // `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn timestamp_at(seconds: u64) -> String {
+        timestamp(UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    #[test]
+    fn formats_the_epoch() {
+        assert_eq!(timestamp_at(0), "19700101T000000Z");
+    }
+
+    #[test]
+    fn formats_times_of_day() {
+        assert_eq!(timestamp_at(1_483_228_799), "20161231T235959Z");
+    }
+
+    #[test]
+    fn handles_leap_days() {
+        assert_eq!(timestamp_at(1_709_208_000), "20240229T120000Z");
+        assert_eq!(timestamp_at(1_709_208_000 + 43_200), "20240301T000000Z");
+    }
+
+    #[test]
+    fn handles_leap_centuries() {
+        assert_eq!(timestamp_at(951_782_400), "20000229T000000Z");
+        assert_eq!(timestamp_at(4_107_542_400 - 1), "21000228T235959Z");
+        assert_eq!(timestamp_at(4_107_542_400), "21000301T000000Z");
+    }
+
+    #[test]
+    fn skips_february_29_in_common_years() {
+        assert_eq!(timestamp_at(1_677_628_800 - 1), "20230228T235959Z");
+        assert_eq!(timestamp_at(1_677_628_800), "20230301T000000Z");
+    }
+
+    #[test]
+    fn clamps_times_before_the_epoch() {
+        assert_eq!(timestamp(UNIX_EPOCH - Duration::from_secs(1)), "19700101T000000Z");
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use super::{link_destination, plan_remove_empty_dirs, Operation, Plan, Result, State};
+
+/// Plans removing every symlink under `plan.target` that points into one of
+/// `package_paths` or an installed package but whose destination no longer
+/// exists, along with any directories that were created for an installed
+/// package and are empty or left empty as a result.
+///
+/// Nothing inside a package, or next to one, is touched, and directories that
+/// can't be read or are on another filesystem are skipped.
+pub fn plan_prune(plan: &mut Plan, package_paths: &[PathBuf], state: &State) -> Result<()> {
+    let mut package_paths = package_paths.to_vec();
+    for record in &state.packages {
+        if !package_paths.contains(&record.path) {
+            package_paths.push(record.path.clone());
+        }
+    }
+
+    let target = plan.target.clone();
+    let device = fs::metadata(&target)?.dev();
+    plan_prune_dir(&target, device, &package_paths, plan)?;
+
+    let recorded_dirs: Vec<PathBuf> = state
+        .packages
+        .iter()
+        .flat_map(|record| record.dirs.iter())
+        .filter(|dir| dir.starts_with(&target))
+        .cloned()
+        .collect();
+
+    plan_remove_empty_dirs(&recorded_dirs, plan)
+}
+
+/// Whether `path` is a symlink into one of `package_paths` whose destination
+/// doesn't exist.
+pub fn is_dangling_link(path: &Path, package_paths: &[PathBuf]) -> Result<bool> {
+    let destination = match link_destination(path)? {
+        Some(destination) => destination,
+        None => return Ok(false),
+    };
+
+    let is_known = package_paths.iter().any(|package_path| destination.starts_with(package_path));
+    Ok(is_known && fs::symlink_metadata(&destination).is_err())
+}
+
+/// Plans removing the dangling links in `dir` and the directories in it on
+/// the filesystem `device`.
+fn plan_prune_dir(
+    dir: &Path,
+    device: u64,
+    package_paths: &[PathBuf],
+    plan: &mut Plan,
+) -> Result<()> {
+    let is_package_dir = package_paths.iter().any(|package_path| {
+        dir.starts_with(package_path) || package_path.parent() == Some(dir)
+    });
+
+    if is_package_dir {
+        return Ok(());
+    }
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) => {
+            plan.operations.push(Operation::Skip {
+                path: dir.to_owned(),
+                reason: error.to_string(),
+            });
+            return Ok(());
+        }
+    };
+
+    for entry_result in entries {
+        let entry = entry_result?;
+        let path = entry.path();
+
+        if plan.is_removed(&path) {
+            continue;
+        }
+
+        if is_dangling_link(&path, package_paths)? {
+            plan.operations.push(Operation::RemovePath { path });
+        } else if entry.file_type()?.is_dir() && entry.metadata()?.dev() == device {
+            plan_prune_dir(&path, device, package_paths, plan)?;
+        }
+    }
+
+    Ok(())
+}
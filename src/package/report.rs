@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::{Action, Conflict, Error, InstallStatus, LinkStatus};
+
+/// Something that happened while planning, executing, or checking a package.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    PackageStarted {
+        action: Action,
+        package: PathBuf,
+        target: PathBuf,
+        dry_run: bool,
+    },
+    LinkCreated { source: PathBuf, target: PathBuf },
+    FileCopied { source: PathBuf, target: PathBuf },
+    HardLinkCreated { source: PathBuf, target: PathBuf },
+    DirCreated { path: PathBuf },
+    DirRemoved { path: PathBuf },
+    TemplateRendered { source: PathBuf, target: PathBuf },
+    LinkRemoved { target: PathBuf },
+    /// A link that was already installed.
+    LinkUnchanged { target: PathBuf },
+    /// A path that was left alone rather than removed, e.g. because it was
+    /// changed by hand.
+    LinkSkipped { target: PathBuf, reason: String },
+    BackedUp { path: PathBuf, backup: PathBuf },
+    Adopted { target: PathBuf, source: PathBuf },
+    Restored { backup: PathBuf, original: PathBuf },
+    Unfolded { path: PathBuf, source: PathBuf },
+    Folded { path: PathBuf, source: PathBuf },
+    /// A hook about to run, or that would run in a dry run, along with the
+    /// environment variables it runs with.
+    HookStarted {
+        name: String,
+        command: Option<String>,
+        script: Option<PathBuf>,
+        dir: PathBuf,
+        env: BTreeMap<String, String>,
+    },
+    HookFinished {
+        name: String,
+        success: bool,
+        stdout: String,
+        stderr: String,
+    },
+    Conflict { conflict: Conflict },
+    RolledBack { package: PathBuf },
+    PackageFinished { action: Action, package: PathBuf },
+    /// Any failure while planning, executing, or checking a package, with
+    /// `kind` from `Error::kind`.
+    PackageFailed {
+        package: PathBuf,
+        kind: String,
+        message: String,
+    },
+    /// A failure that isn't specific to one package, e.g. a dependency cycle
+    /// among the packages given.
+    Failed { kind: String, message: String },
+    LinkChecked { target: PathBuf, status: LinkStatus },
+    PackageChecked {
+        package: PathBuf,
+        linked: usize,
+        total: usize,
+    },
+    PackageListed {
+        package: PathBuf,
+        name: String,
+        status: InstallStatus,
+    },
+}
+
+impl Event {
+    pub fn package_failed(package: &Path, error: &Error) -> Event {
+        Event::PackageFailed {
+            package: package.to_owned(),
+            kind: error.kind().to_owned(),
+            message: error.to_string(),
+        }
+    }
+
+    pub fn failed(error: &Error) -> Event {
+        Event::Failed {
+            kind: error.kind().to_owned(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Receives events as they happen, e.g. to show them to the user.
+pub trait Reporter {
+    fn report(&self, event: &Event);
+
+    /// Called once nothing more will be reported.
+    fn finish(&self) {}
+}
+
+impl<R: Reporter + ?Sized> Reporter for Box<R> {
+    fn report(&self, event: &Event) {
+        (**self).report(event)
+    }
+
+    fn finish(&self) {
+        (**self).finish()
+    }
+}
+
+/// A `Reporter` that ignores everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullReporter;
+
+impl Reporter for NullReporter {
+    fn report(&self, _event: &Event) {}
+}
@@ -0,0 +1,227 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use shellexpand;
+use toml;
+
+use super::{Mode, Result};
+
+const DEFAULT_STATE_HOME: &str = "~/.local/state";
+const STATE_DIRNAME: &str = "scrubjay";
+const STATE_FILENAME: &str = "state.toml";
+const BACKUPS_DIRNAME: &str = "backups";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkRecord {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    #[serde(default, skip_serializing_if = "Mode::is_symlink")]
+    pub mode: Mode,
+    /// For files written rather than linked, the checksum of what was
+    /// written, to tell whether they've been changed since.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+/// A file that was moved out of the way when a package was installed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupRecord {
+    pub original: PathBuf,
+    pub backup: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub path: PathBuf,
+    pub target: PathBuf,
+    #[serde(default)]
+    pub hooks: Vec<String>,
+    /// Directories that were created or unfolded in the target, which are
+    /// removed again on uninstall if nothing else is left in them, and are
+    /// the only directories that may be folded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dirs: Vec<PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkRecord>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backups: Vec<BackupRecord>,
+}
+
+impl Record {
+    pub fn new(path: &Path, target: &Path) -> Record {
+        Record {
+            path: path.to_owned(),
+            target: target.to_owned(),
+            hooks: Vec::new(),
+            dirs: Vec::new(),
+            links: Vec::new(),
+            backups: Vec::new(),
+        }
+    }
+
+    pub fn add_link(&mut self, link: LinkRecord) {
+        self.links.retain(|existing| existing.target != link.target);
+        self.links.push(link);
+    }
+
+    pub fn add_dir(&mut self, path: &Path) {
+        if !self.dirs.iter().any(|existing| existing == path) {
+            self.dirs.push(path.to_owned());
+        }
+    }
+
+    pub fn add_hook(&mut self, hook_name: &str) {
+        if !self.hooks.iter().any(|existing| existing == hook_name) {
+            self.hooks.push(hook_name.to_owned());
+        }
+    }
+}
+
+/// Everything Scrubjay has installed on this machine, persisted between runs
+/// so that uninstalling doesn't depend on the current contents of a package.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub packages: Vec<Record>,
+}
+
+impl State {
+    pub fn dir() -> Result<PathBuf> {
+        let state_home = match env::var("XDG_STATE_HOME") {
+            Ok(ref path_str) if !path_str.is_empty() => PathBuf::from(path_str),
+            _ => PathBuf::from(shellexpand::full(DEFAULT_STATE_HOME)?.into_owned()),
+        };
+
+        Ok(state_home.join(STATE_DIRNAME))
+    }
+
+    pub fn path() -> Result<PathBuf> {
+        Ok(State::dir()?.join(STATE_FILENAME))
+    }
+
+    pub fn backups_dir() -> Result<PathBuf> {
+        Ok(State::dir()?.join(BACKUPS_DIRNAME))
+    }
+
+    pub fn load() -> Result<State> {
+        let mut file = match File::open(State::path()?) {
+            Ok(file) => file,
+            Err(_) => return Ok(State::default()),
+        };
+
+        let mut toml_str = String::new();
+        file.read_to_string(&mut toml_str)?;
+        Ok(toml::from_str(&toml_str)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = State::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let toml_str = toml::to_string(self)?;
+        let mut file = File::create(path)?;
+        file.write_all(toml_str.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn record(&self, package_path: &Path) -> Option<&Record> {
+        self.packages.iter().find(|record| record.path == package_path)
+    }
+
+    /// The installed package that linked `path` or a directory containing it.
+    pub fn owner(&self, path: &Path) -> Option<&Record> {
+        self.packages.iter().find(|record| {
+            record.links.iter().any(|link| path.starts_with(&link.target))
+        })
+    }
+
+    /// The installed package other than `package_path` that contains
+    /// `path`, e.g. the package a symlink points into.
+    pub fn other_owner(&self, path: &Path, package_path: &Path) -> Option<&Record> {
+        self.packages.iter().find(|record| {
+            record.path != package_path && path.starts_with(&record.path) &&
+                !package_path.starts_with(&record.path)
+        })
+    }
+
+    /// Whether `path` is a directory that Scrubjay created or unfolded for
+    /// any installed package.
+    pub fn is_recorded_dir(&self, path: &Path) -> bool {
+        self.packages.iter().any(|record| record.dirs.iter().any(|dir| dir == path))
+    }
+
+    pub fn remove_record(&mut self, package_path: &Path) -> Option<Record> {
+        let index = self.packages.iter().position(|record| record.path == package_path)?;
+        Some(self.packages.remove(index))
+    }
+
+    pub fn insert_record(&mut self, record: Record) {
+        self.remove_record(&record.path);
+        self.packages.push(record);
+    }
+
+    pub fn record_mut(&mut self, package_path: &Path, target: &Path) -> &mut Record {
+        let index = match self.packages.iter().position(|record| record.path == package_path) {
+            Some(index) => index,
+            None => {
+                self.packages.push(Record::new(package_path, target));
+                self.packages.len() - 1
+            }
+        };
+
+        &mut self.packages[index]
+    }
+
+    /// Records `link` for whichever package its source belongs to.
+    pub fn add_link(&mut self, link: LinkRecord) {
+        let owner = self.packages.iter_mut().find(|record| link.source.starts_with(&record.path));
+        if let Some(record) = owner {
+            record.add_link(link);
+        }
+    }
+
+    pub fn remove_dir(&mut self, path: &Path) {
+        for record in &mut self.packages {
+            record.dirs.retain(|dir| dir != path);
+        }
+    }
+
+    pub fn remove_link(&mut self, target: &Path) {
+        for record in &mut self.packages {
+            record.links.retain(|link| link.target != target);
+        }
+    }
+
+    /// Replaces the recorded link at `target`, whichever package owns it, with
+    /// links to the individual entries of the directory it pointed to.
+    pub fn unfold_link(&mut self, target: &Path, links: Vec<LinkRecord>) {
+        for record in &mut self.packages {
+            if let Some(index) = record.links.iter().position(|link| link.target == target) {
+                record.links.remove(index);
+                record.links.extend(links);
+                return;
+            }
+        }
+    }
+
+    /// The inverse of `unfold_link`: replaces the recorded links to entries of
+    /// `link.source` inside `link.target` with `link` itself.
+    pub fn fold_links(&mut self, link: LinkRecord) {
+        for record in &mut self.packages {
+            let is_folded = |existing: &LinkRecord| {
+                existing.target.parent() == Some(&link.target) &&
+                    existing.source.parent() == Some(&link.source)
+            };
+
+            if record.links.iter().any(&is_folded) {
+                record.links.retain(|existing| !is_folded(existing));
+                record.links.push(link);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::result;
+
+use super::Facts;
+use super::conditions;
+
+/// Marks a package file as a template, which is rendered to its target path
+/// without this suffix instead of being linked.
+pub const SUFFIX: &str = ".tmpl";
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+const ENV_PREFIX: &str = "env.";
+
+/// Whether `path` names a template, ignoring any conditions.
+pub fn is_template(path: &Path) -> bool {
+    match path.file_name() {
+        Some(file_name) => {
+            let file_name = file_name.to_string_lossy();
+            let name = conditions::split_name(&file_name).0;
+            name.len() > SUFFIX.len() && name.ends_with(SUFFIX)
+        }
+        None => false,
+    }
+}
+
+/// Removes the template suffix from the last component of `path`.
+pub fn strip_suffix(path: &Path) -> PathBuf {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().into_owned(),
+        None => return path.to_owned(),
+    };
+
+    match file_name.strip_suffix(SUFFIX) {
+        Some(name) if !name.is_empty() => path.with_file_name(name),
+        _ => path.to_owned(),
+    }
+}
+
+/// Replaces each `{{ name }}` in `template` with the value of a package
+/// variable, a fact (`hostname`, `user`, `os` or `arch`), or an environment
+/// variable (`env.NAME`), returning a message if one can't be rendered.
+pub fn render(template: &str, facts: &Facts) -> result::Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(OPEN) {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start + OPEN.len()..];
+
+        let end = match rest.find(CLOSE) {
+            Some(end) => end,
+            None => return Err(format!("unclosed `{}`", OPEN)),
+        };
+
+        let name = rest[..end].trim();
+        match lookup(name, facts) {
+            Some(value) => rendered.push_str(&value),
+            None => return Err(format!("unknown variable `{}`", name)),
+        }
+
+        rest = &rest[end + CLOSE.len()..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// A 64-bit FNV-1a hash of `contents` in hex, which is stable across
+/// versions of Rust, unlike the standard library's hashers.
+pub fn checksum(contents: &[u8]) -> String {
+    let hash = contents.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    format!("{:016x}", hash)
+}
+
+fn lookup(name: &str, facts: &Facts) -> Option<String> {
+    if let Some(var_name) = name.strip_prefix(ENV_PREFIX) {
+        return env::var(var_name).ok();
+    }
+
+    if let Some(value) = facts.vars.get(name) {
+        return Some(value.clone());
+    }
+
+    match name {
+        "hostname" => Some(facts.hostname.clone()),
+        "user" => Some(facts.user.clone()),
+        "os" => Some(facts.os.clone()),
+        "arch" => Some(facts.arch.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn facts() -> Facts {
+        let mut facts = Facts {
+            hostname: "work".to_owned(),
+            user: "jordan".to_owned(),
+            os: "linux".to_owned(),
+            arch: "x86_64".to_owned(),
+            ..Facts::default()
+        };
+        facts.vars.insert("editor".to_owned(), "nvim".to_owned());
+        facts.vars.insert("os".to_owned(), "custom".to_owned());
+        facts
+    }
+
+    #[test]
+    fn renders_facts_and_vars() {
+        let rendered = render("{{ user }}@{{hostname}} uses {{ editor }}", &facts());
+        assert_eq!(rendered, Ok("jordan@work uses nvim".to_owned()));
+    }
+
+    #[test]
+    fn vars_take_precedence_over_facts() {
+        assert_eq!(render("{{ os }}", &facts()), Ok("custom".to_owned()));
+    }
+
+    #[test]
+    fn renders_env_vars() {
+        env::set_var("SCRUBJAY_TEMPLATE_TEST", "value");
+        assert_eq!(render("{{ env.SCRUBJAY_TEMPLATE_TEST }}", &facts()), Ok("value".to_owned()));
+    }
+
+    #[test]
+    fn leaves_text_without_variables_alone() {
+        assert_eq!(render("a } b }} c", &facts()), Ok("a } b }} c".to_owned()));
+    }
+
+    #[test]
+    fn rejects_unclosed_braces() {
+        assert_eq!(render("{{ user }} {{ user", &facts()), Err("unclosed `{{`".to_owned()));
+    }
+
+    #[test]
+    fn rejects_unknown_variables() {
+        assert_eq!(render("{{ shell }}", &facts()), Err("unknown variable `shell`".to_owned()));
+        assert_eq!(
+            render("{{ env.SCRUBJAY_TEMPLATE_TEST_UNSET }}", &facts()),
+            Err("unknown variable `env.SCRUBJAY_TEMPLATE_TEST_UNSET`".to_owned())
+        );
+    }
+
+    #[test]
+    fn checksums_with_fnv_1a() {
+        assert_eq!(checksum(b""), "cbf29ce484222325");
+        assert_eq!(checksum(b"a"), "af63dc4c8601ec8c");
+        assert_eq!(checksum(b"foobar"), "85944171f73967e8");
+    }
+
+    #[test]
+    fn detects_templates_ignoring_conditions() {
+        assert!(is_template(Path::new(".gitconfig.tmpl")));
+        assert!(is_template(Path::new(".gitconfig.tmpl##os.linux")));
+        assert!(!is_template(Path::new(".tmpl")));
+        assert!(!is_template(Path::new(".gitconfig")));
+    }
+
+    #[test]
+    fn strips_suffix() {
+        assert_eq!(strip_suffix(Path::new("a/b.conf.tmpl")), PathBuf::from("a/b.conf"));
+        assert_eq!(strip_suffix(Path::new("a/.tmpl")), PathBuf::from("a/.tmpl"));
+    }
+}
@@ -0,0 +1,67 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Held while `XDG_STATE_HOME` points at a test's own directory, since the
+/// environment is shared by every test in the process.
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// A fresh directory for a test's files, which is removed when it's dropped,
+/// even if the test panics.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let dir_name = format!("scrubjay-test-{}-{}-{}", process::id(), id, name);
+        let path = env::temp_dir().join(dir_name);
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        // Resolved so that paths built from it match canonicalized ones
+        TempDir { path: fs::canonicalize(path).unwrap() }
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, relative_path: P) -> PathBuf {
+        self.path.join(relative_path)
+    }
+
+    /// Creates a directory and any missing parents.
+    pub fn dir<P: AsRef<Path>>(&self, relative_path: P) -> PathBuf {
+        let path = self.join(relative_path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    /// Keeps Scrubjay's state in this directory until the returned guard is
+    /// dropped.
+    pub fn lock_state(&self) -> MutexGuard<'static, ()> {
+        let guard = STATE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        env::set_var("XDG_STATE_HOME", self.join("state"));
+        guard
+    }
+
+    /// Creates a file and any missing parent directories.
+    pub fn file<P: AsRef<Path>>(&self, relative_path: P, contents: &str) -> PathBuf {
+        let path = self.join(relative_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,253 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use super::Result;
+
+const BACKUP_EXTENSION: &str = "scrubjay-backup";
+
+enum Change {
+    CreatedFile(PathBuf),
+    CreatedDir(PathBuf),
+    RemovedDir(PathBuf),
+    /// Where `path` and `backup_path` really were when it was moved aside is
+    /// kept as `real_path` and `real_backup_path`, since either may have been
+    /// reached through a symlink that is replaced later on.
+    Moved {
+        path: PathBuf,
+        backup_path: PathBuf,
+        kept_path: Option<PathBuf>,
+        real_path: PathBuf,
+        real_backup_path: PathBuf,
+    },
+    Renamed { from_path: PathBuf, to_path: PathBuf },
+}
+
+/// A journal of the changes made to the filesystem while installing or
+/// uninstalling a package, so that they can be undone if anything fails.
+///
+/// Removed files are moved aside rather than deleted, and are only deleted
+/// once the transaction is committed.
+#[derive(Default)]
+pub struct Transaction {
+    changes: Vec<Change>,
+}
+
+impl Transaction {
+    pub fn new() -> Transaction {
+        Transaction::default()
+    }
+
+    pub fn symlink(&mut self, source_path: &Path, target_path: &Path) -> Result<()> {
+        symlink(source_path, target_path)?;
+        self.changes.push(Change::CreatedFile(target_path.to_owned()));
+        Ok(())
+    }
+
+    pub fn write_file(&mut self, path: &Path, contents: &[u8]) -> Result<()> {
+        fs::write(path, contents)?;
+        self.changes.push(Change::CreatedFile(path.to_owned()));
+        Ok(())
+    }
+
+    pub fn copy_file(&mut self, source_path: &Path, target_path: &Path) -> Result<()> {
+        fs::copy(source_path, target_path)?;
+        self.changes.push(Change::CreatedFile(target_path.to_owned()));
+        Ok(())
+    }
+
+    pub fn hard_link(&mut self, source_path: &Path, target_path: &Path) -> Result<()> {
+        fs::hard_link(source_path, target_path)?;
+        self.changes.push(Change::CreatedFile(target_path.to_owned()));
+        Ok(())
+    }
+
+    pub fn create_dir(&mut self, path: &Path) -> Result<()> {
+        fs::create_dir(path)?;
+        self.changes.push(Change::CreatedDir(path.to_owned()));
+        Ok(())
+    }
+
+    /// Removes a directory when the transaction is committed, by which time
+    /// anything removed from it earlier in the transaction is gone for good.
+    pub fn remove_dir(&mut self, path: &Path) {
+        self.changes.push(Change::RemovedDir(path.to_owned()));
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Result<()> {
+        self.move_aside(path, None)
+    }
+
+    /// Like `remove`, but moves `path` to `kept_path` instead of deleting it
+    /// when the transaction is committed.
+    pub fn keep(&mut self, path: &Path, kept_path: &Path) -> Result<()> {
+        self.move_aside(path, Some(kept_path.to_owned()))
+    }
+
+    pub fn rename(&mut self, from_path: &Path, to_path: &Path) -> Result<()> {
+        move_path(from_path, to_path)?;
+        self.changes.push(Change::Renamed {
+            from_path: from_path.to_owned(),
+            to_path: to_path.to_owned(),
+        });
+        Ok(())
+    }
+
+    fn move_aside(&mut self, path: &Path, kept_path: Option<PathBuf>) -> Result<()> {
+        let backup_path = backup_path(path);
+        let real_path = real_path_of(path)?;
+        let real_backup_path = real_path_of(&backup_path)?;
+        fs::rename(path, &backup_path)?;
+        self.changes.push(Change::Moved {
+            path: path.to_owned(),
+            backup_path,
+            kept_path,
+            real_path,
+            real_backup_path,
+        });
+        Ok(())
+    }
+
+    /// Whether `path` is where something removed during the transaction was
+    /// moved aside to.
+    pub fn is_backup(&self, path: &Path) -> bool {
+        self.changes.iter().any(|change| match *change {
+            Change::Moved { ref backup_path, .. } => backup_path == path,
+            _ => false,
+        })
+    }
+
+    /// Deletes everything that was removed during the transaction.
+    pub fn commit(self) -> Result<()> {
+        let moved_paths: Vec<(usize, &Path)> = self.changes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, change)| match *change {
+                Change::Moved { ref real_path, .. } => Some((index, real_path.as_path())),
+                _ => None,
+            })
+            .collect();
+
+        for (index, change) in self.changes.iter().enumerate().rev() {
+            if let Change::Moved {
+                ref backup_path,
+                ref kept_path,
+                ref real_backup_path,
+                ..
+            } = *change
+            {
+                // Backups of entries inside a directory that was itself moved
+                // afterward went along with it. Paths are compared where they
+                // really were, since a symlink on the way may have been
+                // replaced with a directory in the meantime, or vice versa
+                let is_nested = moved_paths.iter().any(|&(moved_index, moved_path)| {
+                    moved_index > index && real_backup_path.starts_with(moved_path)
+                });
+
+                match *kept_path {
+                    _ if is_nested => {}
+                    Some(ref kept_path) => {
+                        if let Some(parent) = kept_path.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+
+                        move_path(backup_path, kept_path)?;
+                    }
+                    None => remove_backup(backup_path)?,
+                }
+            }
+        }
+
+        // Directories that aren't empty after all, e.g. because something was
+        // added to them since the plan was made, are left alone
+        for change in &self.changes {
+            if let Change::RemovedDir(ref path) = *change {
+                let _ = fs::remove_dir(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Undoes every change in reverse order, restoring anything that was
+    /// removed.
+    pub fn rollback(self) -> Result<()> {
+        for change in self.changes.into_iter().rev() {
+            match change {
+                Change::CreatedFile(path) => fs::remove_file(path)?,
+                Change::CreatedDir(path) => fs::remove_dir(path)?,
+                Change::RemovedDir(_) => {}
+                Change::Moved { path, backup_path, .. } => fs::rename(backup_path, path)?,
+                Change::Renamed { from_path, to_path } => move_path(&to_path, &from_path)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut backup_path = path.with_file_name(format!("{}.{}", file_name, BACKUP_EXTENSION));
+    let mut count = 1;
+
+    while fs::symlink_metadata(&backup_path).is_ok() {
+        let backup_name = format!("{}.{}-{}", file_name, BACKUP_EXTENSION, count);
+        backup_path = path.with_file_name(backup_name);
+        count += 1;
+    }
+
+    backup_path
+}
+
+/// Where `path` really is, resolving any symlinks in its parent directories
+/// but not `path` itself.
+fn real_path_of(path: &Path) -> Result<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) => Ok(fs::canonicalize(parent)?.join(file_name)),
+        _ => Ok(path.to_owned()),
+    }
+}
+
+fn remove_backup(path: &Path) -> Result<()> {
+    let result = match fs::symlink_metadata(path) {
+        Ok(ref metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(error) => Err(error),
+    };
+
+    match result {
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// Renames `from_path` to `to_path`, copying and deleting it instead if they
+/// are on different filesystems.
+fn move_path(from_path: &Path, to_path: &Path) -> Result<()> {
+    match fs::rename(from_path, to_path) {
+        Err(ref error) if error.kind() == io::ErrorKind::CrossesDevices => {
+            copy_path(from_path, to_path)?;
+            remove_backup(from_path)
+        }
+        result => Ok(result?),
+    }
+}
+
+fn copy_path(from_path: &Path, to_path: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(from_path)?;
+    if metadata.file_type().is_symlink() {
+        symlink(fs::read_link(from_path)?, to_path)?;
+    } else if metadata.is_dir() {
+        fs::create_dir(to_path)?;
+        for entry_result in fs::read_dir(from_path)? {
+            let entry = entry_result?;
+            copy_path(&entry.path(), &to_path.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(from_path, to_path)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,453 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+use colored::*;
+use scrubjay::package::{Action, Event, InstallStatus, LinkStatus, Reporter};
+use serde_json::{self, Map, Value};
+
+const INDENT: &str = "‣ ";
+
+/// Renders events as colored, human-readable lines.
+pub struct HumanReporter {
+    dry_run: bool,
+    verbose: bool,
+    package: RefCell<PathBuf>,
+}
+
+impl HumanReporter {
+    pub fn new(dry_run: bool, verbose: bool) -> HumanReporter {
+        HumanReporter {
+            dry_run,
+            verbose,
+            package: RefCell::new(PathBuf::new()),
+        }
+    }
+
+    fn path_str(&self, absolute_path: &Path) -> ColoredString {
+        let package = self.package.borrow();
+        let path = if absolute_path == *package {
+            absolute_path
+        } else if let Ok(relative_path) = absolute_path.strip_prefix(&*package) {
+            relative_path
+        } else {
+            absolute_path
+        };
+
+        let raw_str = path.to_string_lossy();
+        let safe_str = if raw_str.contains(char::is_whitespace) {
+            format!("`{}`", raw_str)
+        } else {
+            raw_str.to_string()
+        };
+
+        safe_str.bold()
+    }
+
+    fn print_start(&self, action: Action, package: &Path, target: &Path) {
+        let package_str = self.path_str(package);
+        let target_str = self.path_str(target);
+
+        match action {
+            Action::Install | Action::Reinstall => {
+                let verb = if action == Action::Install {
+                    "Installing"
+                } else {
+                    "Reinstalling"
+                };
+
+                println!(
+                    "{} {} {} {}{}",
+                    verb.green(),
+                    package_str,
+                    "to".green(),
+                    target_str,
+                    "...".green(),
+                )
+            }
+            Action::Uninstall => {
+                println!(
+                    "{} {} {} {}{}",
+                    "Uninstalling".green(),
+                    package_str,
+                    "from".green(),
+                    target_str,
+                    "...".green(),
+                )
+            }
+            Action::Adopt => {
+                println!(
+                    "{} {} {} {}{}",
+                    "Adopting".green(),
+                    target_str,
+                    "into".green(),
+                    package_str,
+                    "...".green(),
+                )
+            }
+            Action::RestoreBackup => {
+                println!("{} {}{}", "Restoring".green(), package_str, "...".green())
+            }
+            Action::Prune => println!("{} {}{}", "Pruning".green(), target_str, "...".green()),
+        }
+    }
+
+    fn print_link_status(&self, target: &Path, status: &LinkStatus) {
+        let target_str = self.path_str(target);
+        match *status {
+            LinkStatus::Linked => println!("{}{} {}", INDENT, "Linked".cyan(), target_str),
+            LinkStatus::Copied => println!("{}{} {}", INDENT, "Copied".cyan(), target_str),
+            LinkStatus::Rendered => println!("{}{} {}", INDENT, "Rendered".cyan(), target_str),
+            LinkStatus::Modified => println!("{}{} {}", INDENT, "Modified".yellow(), target_str),
+            LinkStatus::Missing => println!("{}{} {}", INDENT, "Missing".red(), target_str),
+            LinkStatus::Elsewhere(ref destination) => {
+                println!(
+                    "{}{} {} {} {}",
+                    INDENT,
+                    "Linked".yellow(),
+                    target_str,
+                    "to".yellow(),
+                    self.path_str(destination)
+                )
+            }
+            LinkStatus::Dangling(ref destination) => {
+                println!(
+                    "{}{} {} {} {}",
+                    INDENT,
+                    "Dangling".red(),
+                    target_str,
+                    "to".red(),
+                    self.path_str(destination)
+                )
+            }
+            LinkStatus::File => println!("{}{} {}", INDENT, "File exists at".red(), target_str),
+            LinkStatus::Directory => {
+                println!("{}{} {}", INDENT, "Directory exists at".red(), target_str)
+            }
+        }
+    }
+}
+
+impl Reporter for HumanReporter {
+    fn report(&self, event: &Event) {
+        match *event {
+            Event::PackageStarted {
+                action,
+                ref package,
+                ref target,
+                ..
+            } => {
+                *self.package.borrow_mut() = package.clone();
+                self.print_start(action, package, target);
+            }
+            Event::LinkCreated { ref target, .. } if self.verbose || self.dry_run => {
+                println!("{}{} {}", INDENT, "Created".cyan(), self.path_str(target))
+            }
+            Event::DirCreated { ref path } if self.verbose || self.dry_run => {
+                println!("{}{} {}/", INDENT, "Created".cyan(), self.path_str(path))
+            }
+            Event::DirRemoved { ref path } if self.verbose || self.dry_run => {
+                println!("{}{} {}/", INDENT, "Removed".red(), self.path_str(path))
+            }
+            Event::LinkRemoved { ref target } if self.verbose || self.dry_run => {
+                println!("{}{} {}", INDENT, "Removed".red(), self.path_str(target))
+            }
+            Event::FileCopied { ref target, .. } if self.verbose || self.dry_run => {
+                println!("{}{} {}", INDENT, "Copied".cyan(), self.path_str(target))
+            }
+            Event::HardLinkCreated { ref target, .. } if self.verbose || self.dry_run => {
+                println!("{}{} {}", INDENT, "Hard linked".cyan(), self.path_str(target))
+            }
+            Event::TemplateRendered { ref target, .. } if self.verbose || self.dry_run => {
+                println!("{}{} {}", INDENT, "Rendered".cyan(), self.path_str(target))
+            }
+            Event::LinkUnchanged { ref target } if self.verbose => {
+                println!("{}{} {}", INDENT, "Unchanged".blue(), self.path_str(target))
+            }
+            Event::LinkSkipped { ref target, ref reason } => {
+                println!(
+                    "{}{} {} ({})",
+                    INDENT,
+                    "Skipped".yellow(),
+                    self.path_str(target),
+                    reason
+                )
+            }
+            Event::BackedUp { ref path, ref backup } => {
+                println!(
+                    "{}{} {} {} {}",
+                    INDENT,
+                    "Backed up".yellow(),
+                    self.path_str(path),
+                    "to".yellow(),
+                    self.path_str(backup)
+                )
+            }
+            Event::Adopted { ref target, .. } => {
+                println!("{}{} {}", INDENT, "Adopted".yellow(), self.path_str(target))
+            }
+            Event::Restored { ref original, .. } if self.verbose || self.dry_run => {
+                println!("{}{} {}", INDENT, "Restored".cyan(), self.path_str(original))
+            }
+            Event::Unfolded { ref path, .. } if self.verbose || self.dry_run => {
+                println!("{}{} {}", INDENT, "Unfolded".yellow(), self.path_str(path))
+            }
+            Event::Folded { ref path, .. } if self.verbose || self.dry_run => {
+                println!("{}{} {}", INDENT, "Folded".yellow(), self.path_str(path))
+            }
+            Event::HookStarted {
+                ref command,
+                ref script,
+                ref dir,
+                ref env,
+                ..
+            } if self.verbose || self.dry_run => {
+                if let Some(ref command_str) = *command {
+                    println!(
+                        "{}{} `{}` {} {}{}",
+                        INDENT,
+                        "Running command".magenta(),
+                        command_str,
+                        "in".magenta(),
+                        self.path_str(dir),
+                        "...".magenta()
+                    );
+                } else if let Some(ref script_path) = *script {
+                    println!(
+                        "{}{} {} {} {}{}",
+                        INDENT,
+                        "Running script".magenta(),
+                        self.path_str(script_path),
+                        "in".magenta(),
+                        self.path_str(dir),
+                        "...".magenta()
+                    );
+                }
+
+                for (name, value) in env {
+                    println!("  {}={}", name.dimmed(), value);
+                }
+            }
+            Event::HookFinished { ref stdout, .. } if self.verbose => {
+                for line in stdout.lines() {
+                    println!("  {}", line);
+                }
+            }
+            Event::Conflict { ref conflict } => {
+                println!("{}{} {}", INDENT, "Conflict:".red().bold(), conflict)
+            }
+            Event::RolledBack { ref package } => {
+                println!("{} {}", "Rolled back".yellow(), self.path_str(package))
+            }
+            Event::PackageFinished { action, ref package } => {
+                let verb = match action {
+                    Action::Install => "Installed",
+                    Action::Uninstall => "Uninstalled",
+                    Action::Reinstall => "Reinstalled",
+                    Action::Adopt => "Adopted",
+                    Action::RestoreBackup => "Restored",
+                    Action::Prune => "Pruned",
+                };
+
+                println!("{} {}", verb.green(), self.path_str(package));
+            }
+            Event::LinkChecked { ref target, ref status } if !status.is_ok() || self.verbose => {
+                self.print_link_status(target, status)
+            }
+            Event::PackageListed {
+                ref name,
+                ref package,
+                status,
+            } => {
+                let label = match status {
+                    InstallStatus::Installed => "Installed".green(),
+                    InstallStatus::PartiallyInstalled => "Partially installed".yellow(),
+                    InstallStatus::NotInstalled => "Not installed".red(),
+                };
+
+                if self.verbose {
+                    println!("{} {} ({})", name.bold(), label, package.display());
+                } else {
+                    println!("{} {}", name.bold(), label);
+                }
+            }
+            Event::PackageChecked {
+                ref package,
+                linked,
+                total,
+            } => {
+                let label = if linked == total {
+                    "Installed".green()
+                } else if linked == 0 {
+                    "Not installed".red()
+                } else {
+                    "Partially installed".yellow()
+                };
+
+                println!("{} {} ({}/{} linked)", label, self.path_str(package), linked, total);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Collects events into a single JSON document with an object per package,
+/// printed once everything has been reported.
+#[derive(Default)]
+pub struct JsonReporter {
+    packages: RefCell<Vec<Value>>,
+    current: RefCell<Option<Map<String, Value>>>,
+    error: RefCell<Option<Value>>,
+}
+
+impl JsonReporter {
+    pub fn new() -> JsonReporter {
+        JsonReporter::default()
+    }
+
+    fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Map<String, Value>),
+    {
+        let mut current = self.current.borrow_mut();
+        let package = current.get_or_insert_with(|| {
+            let mut package = Map::new();
+            package.insert("operations".to_owned(), Value::Array(Vec::new()));
+            package.insert("conflicts".to_owned(), Value::Array(Vec::new()));
+            package
+        });
+
+        f(package);
+    }
+
+    fn push(&self, key: &str, value: Value) {
+        self.update(|package| {
+            if let Some(&mut Value::Array(ref mut values)) = package.get_mut(key) {
+                values.push(value);
+            }
+        });
+    }
+
+    fn finish_package(&self, result: &str) {
+        self.update(|package| {
+            package.insert("result".to_owned(), Value::from(result));
+        });
+
+        if let Some(package) = self.current.borrow_mut().take() {
+            self.packages.borrow_mut().push(Value::Object(package));
+        }
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn report(&self, event: &Event) {
+        let mut value = serde_json::to_value(event).unwrap_or(Value::Null);
+
+        match *event {
+            Event::PackageStarted { .. } => {
+                self.update(|package| {
+                    if let Value::Object(fields) = value {
+                        package.extend(fields.into_iter().filter(|(key, _)| key != "event"));
+                    }
+                })
+            }
+            Event::Conflict { ref conflict } => {
+                self.push("conflicts", serde_json::to_value(conflict).unwrap_or(Value::Null))
+            }
+            Event::RolledBack { .. } => {
+                self.update(|package| {
+                    package.insert("rolled_back".to_owned(), Value::Bool(true));
+                })
+            }
+            Event::PackageFinished { .. } => self.finish_package("ok"),
+            Event::PackageFailed {
+                ref package,
+                ref kind,
+                ref message,
+            } => {
+                self.update(|fields| {
+                    fields.entry("package").or_insert_with(|| Value::from(package.to_string_lossy()));
+
+                    let mut error = Map::new();
+                    error.insert("kind".to_owned(), Value::from(kind.as_str()));
+                    error.insert("message".to_owned(), Value::from(message.as_str()));
+                    fields.insert("error".to_owned(), Value::Object(error));
+                });
+                self.finish_package("error");
+            }
+            Event::Failed { .. } => {
+                if let Value::Object(ref mut fields) = value {
+                    fields.remove("event");
+                }
+
+                *self.error.borrow_mut() = Some(value);
+            }
+            Event::PackageListed { .. } => {
+                self.update(|package| {
+                    if let Value::Object(fields) = value {
+                        package.extend(fields.into_iter().filter(|(key, _)| key != "event"));
+                    }
+
+                    package.insert("action".to_owned(), Value::from("list"));
+                    package.remove("operations");
+                    package.remove("conflicts");
+                });
+                self.finish_package("ok");
+            }
+            Event::PackageChecked {
+                ref package,
+                linked,
+                total,
+            } => {
+                self.update(|fields| {
+                    fields.insert("package".to_owned(), Value::from(package.to_string_lossy()));
+                    fields.insert("action".to_owned(), Value::from("status"));
+                    fields.insert("linked".to_owned(), Value::from(linked));
+                    fields.insert("total".to_owned(), Value::from(total));
+                });
+                self.finish_package(if linked == total { "ok" } else { "out_of_sync" });
+            }
+            _ => {
+                let is_dry_run = self.current.borrow().as_ref().is_some_and(|package| {
+                    package.get("dry_run") == Some(&Value::Bool(true))
+                });
+
+                let result = match *event {
+                    Event::LinkChecked { ref status, .. } if !status.is_ok() => "out_of_sync",
+                    Event::HookFinished { success: false, .. } => "error",
+                    _ if is_dry_run => "planned",
+                    _ => "ok",
+                };
+
+                if let Value::Object(ref mut fields) = value {
+                    fields.insert("result".to_owned(), Value::from(result));
+                }
+
+                self.push("operations", value)
+            }
+        }
+    }
+
+    fn finish(&self) {
+        let mut document = Map::new();
+        document.insert("packages".to_owned(), Value::Array(self.packages.replace(Vec::new())));
+
+        if let Some(error) = self.error.borrow_mut().take() {
+            document.insert("error".to_owned(), error);
+        }
+
+        if let Ok(json_str) = serde_json::to_string_pretty(&document) {
+            println!("{}", json_str);
+        }
+    }
+}
+
+/// Prints each event as a line of JSON as soon as it happens.
+pub struct JsonLinesReporter;
+
+impl Reporter for JsonLinesReporter {
+    fn report(&self, event: &Event) {
+        if let Ok(json_str) = serde_json::to_string(event) {
+            println!("{}", json_str);
+        }
+    }
+}